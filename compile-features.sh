@@ -5,5 +5,7 @@ cargo c
 cargo c --features=serde
 cargo c --features=term-line
 cargo c --features=json-printer
+cargo c --features=tokio
+cargo c --features=rayon
 cargo c --all-features
 cargo c --all-features --all-targets
@@ -30,13 +30,17 @@ impl Consume for SimplePrinter {
             State::Cancelled => {
                 println!("cancelled")
             }
+            State::Abandoned => {
+                println!("abandoned")
+            }
+            _ => println!(),
         }
     }
 }
 
 fn main() {
     // initialise the consumer
-    howudoin::init(SimplePrinter);
+    let _guard = howudoin::init_guarded(SimplePrinter);
 
     let rpt = howudoin::new().label("Progress").set_len(20);
 
@@ -2,7 +2,7 @@ use std::{thread::*, time::Duration};
 
 fn main() {
     // initialise the JsonPrinter consumer
-    howudoin::init(howudoin::consumers::JsonPrinter::default());
+    let _guard = howudoin::init_guarded(howudoin::consumers::JsonPrinter::default());
 
     // build a new parent reporter
     let parent_rpt = howudoin::new().label("Parent").set_len(5);
@@ -27,6 +27,5 @@ fn main() {
     }
 
     parent_rpt.finish();
-
-    sleep(Duration::from_millis(100));
+    // the guard shuts down the consumer loop, displaying the finished report
 }
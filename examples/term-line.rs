@@ -5,7 +5,7 @@ fn main() {
     spawn_cancellation_detector();
 
     // initialise the TermLine consumer
-    howudoin::init(howudoin::consumers::TermLine::new());
+    let _guard = howudoin::init_guarded(howudoin::consumers::TermLine::new());
 
     // build a new parent reporter
     let parent_rpt = howudoin::new().label("Parent").set_len(5);
@@ -39,8 +39,7 @@ fn main() {
     }
 
     parent_rpt.finish();
-
-    sleep(Duration::from_millis(100));
+    // the guard shuts down the consumer loop, displaying the finished report
 }
 
 fn spawn_cancellation_detector() {
@@ -0,0 +1,369 @@
+use super::*;
+use flume::{bounded, unbounded, Sender};
+use std::thread::JoinHandle;
+
+/// A configurable initialiser of the progress consumer loop.
+///
+/// [`init`] uses the default configuration: an unbounded channel, with 500 millisecond
+/// timeouts.
+///
+/// # Example
+/// ```rust
+/// use howudoin::{Backpressure, Builder};
+/// use std::time::Duration;
+///
+/// let handle = Builder::new()
+///     .capacity(1024)
+///     .backpressure(Backpressure::DropCoalescible)
+///     .fetch_timeout(Duration::from_secs(1))
+///     .thread_name("progress")
+///     .init(howudoin::consumers::Noop::default());
+/// ```
+#[derive(Debug)]
+pub struct Builder {
+    capacity: Option<usize>,
+    cfg: Config,
+    thread_name: Option<String>,
+    fallback: Option<rx::Replacement>,
+}
+
+/// The behaviour when sending to a full channel.
+///
+/// This only applies to bounded channels, see [`Builder::capacity`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Backpressure {
+    /// Block until there is space in the channel.
+    ///
+    /// Report creation blocks for at most the [`Builder::create_timeout`].
+    #[default]
+    Block,
+    /// Drop the update being sent.
+    ///
+    /// Report creation is also dropped, returning a [`Tx`] not connected to the consumer loop.
+    /// [`fetch`] requests still wait for space, up to the [`Builder::fetch_timeout`], and
+    /// cancelling or resetting always waits for space.
+    DropNewest,
+    /// Drop position change notifications, blocking for the rest.
+    ///
+    /// Position updates are not lost, since the consumer loop samples the positions on each
+    /// debounce tick.
+    DropCoalescible,
+}
+
+/// The channel configuration, shared with the producers.
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    backpressure: Backpressure,
+    pub(crate) fetch_timeout: Duration,
+    create_timeout: Duration,
+    pub(crate) shutdown_timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            backpressure: Backpressure::Block,
+            fetch_timeout: Duration::from_millis(500),
+            create_timeout: Duration::from_millis(500),
+            shutdown_timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Config {
+    /// Send the payload, applying the backpressure policy.
+    ///
+    /// Returns if the payload was sent.
+    pub(crate) fn send(&self, tx: &Sender<Payload>, payload: Payload) -> bool {
+        if let Payload::Fetch(_) = payload {
+            // fetching is a request, it always waits
+            return tx.send_timeout(payload, self.fetch_timeout).is_ok();
+        }
+
+        let block = match self.backpressure {
+            _ if payload.is_control() => true,
+            Backpressure::Block => true,
+            Backpressure::DropNewest => false,
+            Backpressure::DropCoalescible => !payload.is_coalescible(),
+        };
+
+        if !block {
+            tx.try_send(payload).is_ok()
+        } else if payload.is_creation() {
+            tx.send_timeout(payload, self.create_timeout).is_ok()
+        } else {
+            tx.send(payload).is_ok()
+        }
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Builder {
+    /// A builder with the default configuration.
+    pub fn new() -> Self {
+        Self {
+            capacity: None,
+            cfg: Config::default(),
+            thread_name: None,
+            fallback: None,
+        }
+    }
+
+    /// Use a bounded channel with `capacity`.
+    ///
+    /// By default the channel is unbounded.
+    /// The behaviour when the channel is full is set with [`Builder::backpressure`].
+    /// A [`Pump`] requires an unbounded channel.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Use an unbounded channel.
+    pub fn unbounded(mut self) -> Self {
+        self.capacity = None;
+        self
+    }
+
+    /// Set the behaviour when sending to a full channel.
+    pub fn backpressure(mut self, backpressure: Backpressure) -> Self {
+        self.cfg.backpressure = backpressure;
+        self
+    }
+
+    /// Set the time [`fetch`] waits for a reply from the consumer loop.
+    ///
+    /// Defaults to 500 milliseconds.
+    pub fn fetch_timeout(mut self, timeout: Duration) -> Self {
+        self.cfg.fetch_timeout = timeout;
+        self
+    }
+
+    /// Set the time report creation waits for space in a full channel, with
+    /// [`Backpressure::Block`].
+    ///
+    /// If the timeout elapses, the returned [`Tx`] is not connected to the consumer loop.
+    /// Defaults to 500 milliseconds.
+    pub fn create_timeout(mut self, timeout: Duration) -> Self {
+        self.cfg.create_timeout = timeout;
+        self
+    }
+
+    /// Set the time [`shutdown`] waits for the consumer loop to exit.
+    ///
+    /// Defaults to 1 second.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.cfg.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Set the name of the consumer loop's thread.
+    pub fn thread_name<N: Into<String>>(mut self, name: N) -> Self {
+        self.thread_name = Some(name.into());
+        self
+    }
+
+    /// Set a consumer to fall back to if the consumer panics.
+    ///
+    /// Panics from the consumer's callbacks are caught, and the consumer is dropped.
+    /// Without a fallback, the consumer loop keeps tracking progress without a consumer.
+    /// The panics are recorded in the [`stats`].
+    pub fn fallback<C>(mut self, consumer: C) -> Self
+    where
+        C: IntoConsumer,
+        C::Consumer: Send + 'static,
+    {
+        self.fallback = Some(rx::Replacement(Box::new(consumer.into_consumer())));
+        self
+    }
+
+    /// Initialise the global progress consumer loop.
+    ///
+    /// See [`init`].
+    pub fn init<C>(self, consumer: C) -> Handle
+    where
+        C: IntoConsumer,
+        C::Consumer: Send + 'static,
+    {
+        self.init_on(Howudoin::global(), consumer)
+    }
+
+    /// Initialise the progress consumer loop of the instance `hd`.
+    pub fn init_on<C>(mut self, hd: &Howudoin, consumer: C) -> Handle
+    where
+        C: IntoConsumer,
+        C::Consumer: Send + 'static,
+    {
+        let mut thread = std::thread::Builder::new();
+        if let Some(name) = self.thread_name.take() {
+            thread = thread.name(name);
+        }
+
+        let looper = self.looper::<dyn Consume + Send>(hd, Box::new(consumer.into_consumer()));
+        let join = thread
+            .spawn(move || looper.run())
+            .expect("failed to spawn consumer loop thread");
+
+        Handle {
+            hd: hd.clone(),
+            join,
+        }
+    }
+
+    /// Initialise the global progress consumer loop as a tokio task. Requires `tokio` feature.
+    ///
+    /// See [`init_async`].
+    #[cfg(feature = "tokio")]
+    pub fn init_async<C>(self, consumer: C) -> tokio::task::JoinHandle<()>
+    where
+        C: IntoConsumer,
+        C::Consumer: Send + 'static,
+    {
+        self.init_async_on(Howudoin::global(), consumer)
+    }
+
+    /// Initialise the progress consumer loop of the instance `hd` as a tokio task. Requires
+    /// `tokio` feature.
+    ///
+    /// See [`init_async`].
+    #[cfg(feature = "tokio")]
+    pub fn init_async_on<C>(self, hd: &Howudoin, consumer: C) -> tokio::task::JoinHandle<()>
+    where
+        C: IntoConsumer,
+        C::Consumer: Send + 'static,
+    {
+        let looper = self.looper::<dyn Consume + Send>(hd, Box::new(consumer.into_consumer()));
+        tokio::spawn(looper.run_async())
+    }
+
+    /// Initialise the global progress reporter, returning the receiver side as a [`Pump`] to be
+    /// driven from the caller's thread.
+    ///
+    /// No thread is spawned, and the [`Builder::thread_name`] is ignored.
+    /// Since the consumer is invoked on the pumping thread, it does not need to be `Send`.
+    ///
+    /// # Panics
+    /// Panics if a [`Builder::capacity`] is set: the reporters usually run on the pumping thread,
+    /// so waiting for space in a full channel would deadlock.
+    pub fn pump<C>(self, consumer: C) -> Pump
+    where
+        C: IntoConsumer,
+        C::Consumer: 'static,
+    {
+        self.pump_on(Howudoin::global(), consumer)
+    }
+
+    /// Initialise the progress reporter of the instance `hd`, returning the receiver side as a
+    /// [`Pump`].
+    ///
+    /// See [`Builder::pump`].
+    pub fn pump_on<C>(self, hd: &Howudoin, consumer: C) -> Pump
+    where
+        C: IntoConsumer,
+        C::Consumer: 'static,
+    {
+        assert!(
+            self.capacity.is_none(),
+            "a pump requires an unbounded channel"
+        );
+        Pump::new(self.looper::<dyn Consume>(hd, Box::new(consumer.into_consumer())))
+    }
+
+    /// Connect the instance `hd` to a new channel, returning the receiver side.
+    fn looper<B: ?Sized + rx::Slot>(self, hd: &Howudoin, consumer: Box<B>) -> rx::Looper<B> {
+        let Self {
+            capacity,
+            cfg,
+            thread_name: _,
+            fallback,
+        } = self;
+
+        let (tx, rx) = match capacity {
+            Some(x) => bounded(x),
+            None => unbounded(),
+        };
+
+        // the pump holds the sender until it finishes
+        let (done_tx, done) = bounded::<()>(0);
+
+        let stats = Arc::default();
+        let consumer = rx::Guarded::new(
+            consumer,
+            fallback.map(|x| B::from_send(x.0)),
+            Arc::clone(&stats),
+        );
+
+        hd.set_tx(tx, done, stats, cfg);
+
+        rx::Looper::new(rx, consumer, done_tx)
+    }
+}
+
+/// A handle to an initialised consumer loop.
+///
+/// Dropping the handle detaches the consumer loop thread.
+pub struct Handle {
+    hd: Howudoin,
+    join: JoinHandle<()>,
+}
+
+impl Handle {
+    /// The instance the consumer loop belongs to.
+    pub fn instance(&self) -> &Howudoin {
+        &self.hd
+    }
+
+    /// The consumer loop's thread.
+    pub fn thread(&self) -> &std::thread::Thread {
+        self.join.thread()
+    }
+
+    /// The consumer loop has exited.
+    pub fn is_finished(&self) -> bool {
+        self.join.is_finished()
+    }
+
+    /// Unwrap the consumer loop thread's [`JoinHandle`].
+    pub fn into_join_handle(self) -> JoinHandle<()> {
+        self.join
+    }
+
+    /// [Shutdown](Howudoin::shutdown) the consumer loop and join its thread.
+    ///
+    /// Unlike [`shutdown`], this waits for the thread without a timeout, returning an error if the
+    /// consumer panicked.
+    pub fn shutdown(self) -> std::thread::Result<()> {
+        self.hd.disable();
+        self.join.join()
+    }
+}
+
+/// Shuts down the consumer loop when dropped.
+///
+/// Returned from [`init_guarded`].
+#[must_use = "the consumer loop is shutdown when the guard is dropped"]
+pub struct ShutdownGuard {
+    hd: Howudoin,
+}
+
+impl ShutdownGuard {
+    pub(crate) fn new(hd: Howudoin) -> Self {
+        Self { hd }
+    }
+
+    /// The instance the consumer loop belongs to.
+    pub fn instance(&self) -> &Howudoin {
+        &self.hd
+    }
+}
+
+impl Drop for ShutdownGuard {
+    fn drop(&mut self) {
+        self.hd.shutdown();
+    }
+}
@@ -0,0 +1,368 @@
+use crate::*;
+use report::{Event, Report};
+use std::collections::BTreeSet;
+
+type Predicate = Box<dyn FnMut(&Report, Id, Option<Id>, &Controller) -> bool + Send>;
+
+/// Forward the callbacks which are passed through unchanged to `self.inner`.
+macro_rules! forward_hooks {
+    () => {
+        fn on_start(&mut self) {
+            self.inner.on_start()
+        }
+
+        fn on_tick(&mut self, controller: &Controller) {
+            self.inner.on_tick(controller)
+        }
+
+        fn on_shutdown(&mut self, controller: &Controller) {
+            self.inner.on_shutdown(controller)
+        }
+    };
+}
+
+/// Extension trait for composing consumers.
+///
+/// The combinators wrap a consumer, and can be chained.
+///
+/// # Example
+/// ```rust
+/// use howudoin::{consumers::Noop, ConsumeExt};
+/// use std::time::Duration;
+///
+/// let consumer = Noop::default()
+///     .filter(|rpt, _, _| !rpt.label.starts_with("internal"))
+///     .max_depth(2)
+///     .map_report(|rpt, _| rpt.label = rpt.label.to_uppercase())
+///     .throttle(Duration::from_millis(500));
+///
+/// howudoin::init(consumer);
+/// ```
+pub trait ConsumeExt: Consume + Sized {
+    /// Only deliver the reports which satisfy the predicate.
+    ///
+    /// A delivered report which no longer satisfies the predicate is delivered as closed.
+    /// Events are forwarded for reports which satisfy the predicate when the event is emitted,
+    /// along with the events which apply to all reports.
+    /// Note that the children of a filtered report are still delivered, with their parent
+    /// identifier referencing the filtered report.
+    fn filter<F>(self, mut f: F) -> Filter<Self>
+    where
+        F: FnMut(&Report, Id, Option<Id>) -> bool + Send + 'static,
+    {
+        Filter::new(self, Box::new(move |r, id, p, _| f(r, id, p)))
+    }
+
+    /// Only deliver the reports with at least `depth` ancestors.
+    ///
+    /// Root reports have a depth of zero.
+    fn min_depth(self, depth: usize) -> Filter<Self> {
+        Filter::new(
+            self,
+            Box::new(move |_, id, _, c| c.depth(id).is_some_and(|d| d >= depth)),
+        )
+    }
+
+    /// Only deliver the reports with at most `depth` ancestors.
+    ///
+    /// Root reports have a depth of zero.
+    fn max_depth(self, depth: usize) -> Filter<Self> {
+        Filter::new(
+            self,
+            Box::new(move |_, id, _, c| c.depth(id).is_some_and(|d| d <= depth)),
+        )
+    }
+
+    /// Modify the reports before they are delivered.
+    ///
+    /// `f` is invoked with a copy of each changed report.
+    /// Events are forwarded unchanged.
+    fn map_report<F>(self, f: F) -> MapReport<Self, F>
+    where
+        F: FnMut(&mut Report, Id),
+    {
+        MapReport { inner: self, f }
+    }
+
+    /// Deliver changes at most once every `period`.
+    ///
+    /// This raises the consumer's [`Consume::debounce`] to `period`, so changes are accumulated
+    /// in between. Events are forwarded unthrottled.
+    fn throttle(self, period: Duration) -> Throttle<Self> {
+        Throttle {
+            inner: self,
+            period,
+        }
+    }
+}
+
+impl<C: Consume> ConsumeExt for C {}
+
+// ###### FILTER ###############################################################
+
+/// Consumer for [`ConsumeExt::filter`], [`ConsumeExt::min_depth`], and
+/// [`ConsumeExt::max_depth`].
+pub struct Filter<C> {
+    inner: C,
+    pred: Predicate,
+    /// The reports which have been delivered.
+    shown: BTreeSet<Id>,
+    /// The reports which events have been forwarded for.
+    forwarded: BTreeSet<Id>,
+}
+
+impl<C> Filter<C> {
+    fn new(inner: C, pred: Predicate) -> Self {
+        Self {
+            inner,
+            pred,
+            shown: BTreeSet::new(),
+            forwarded: BTreeSet::new(),
+        }
+    }
+
+    /// Unwrap the inner consumer.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C: Consume> Consume for Filter<C> {
+    fn debounce(&self) -> Duration {
+        self.inner.debounce()
+    }
+
+    fn rpt(&mut self, report: &Report, id: Id, parent: Option<Id>, controller: &Controller) {
+        self.batch(&[Change::Updated { report, id, parent }], controller);
+    }
+
+    fn closed(&mut self, id: Id) {
+        if self.shown.remove(&id) {
+            self.inner.closed(id);
+        }
+    }
+
+    fn batch(&mut self, changes: &[Change], controller: &Controller) {
+        let changes = changes
+            .iter()
+            .filter_map(|x| match *x {
+                Change::Updated { report, id, parent } => {
+                    if (self.pred)(report, id, parent, controller) {
+                        self.shown.insert(id);
+                        Some(*x)
+                    } else {
+                        // hide a report which was previously delivered
+                        self.shown.remove(&id).then_some(Change::Closed(id))
+                    }
+                }
+                Change::Closed(id) => self.shown.remove(&id).then_some(*x),
+            })
+            .collect::<Vec<_>>();
+
+        if !changes.is_empty() {
+            self.inner.batch(&changes, controller);
+        }
+    }
+
+    fn event(&mut self, event: &Event, controller: &Controller) {
+        // events are emitted before the changes are batched, so evaluate the predicate here
+        let show = match event.id().map(|id| controller.change(id)) {
+            None => true,
+            Some(Change::Updated { report, id, parent }) => {
+                let show = (self.pred)(report, id, parent, controller);
+                if show {
+                    self.forwarded.insert(id);
+                }
+                show
+            }
+            // the report is no longer tracked
+            Some(Change::Closed(id)) => match event {
+                Event::Closed(_) => self.forwarded.remove(&id),
+                _ => self.forwarded.contains(&id),
+            },
+        };
+
+        if show {
+            self.inner.event(event, controller);
+        }
+    }
+
+    fn on_reset(&mut self, controller: &Controller) {
+        self.shown.clear();
+        self.forwarded.clear();
+        self.inner.on_reset(controller);
+    }
+
+    forward_hooks!();
+}
+
+// ###### MAP ##################################################################
+
+/// Consumer for [`ConsumeExt::map_report`].
+pub struct MapReport<C, F> {
+    inner: C,
+    f: F,
+}
+
+impl<C, F> MapReport<C, F> {
+    /// Unwrap the inner consumer.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C, F> Consume for MapReport<C, F>
+where
+    C: Consume,
+    F: FnMut(&mut Report, Id),
+{
+    fn debounce(&self) -> Duration {
+        self.inner.debounce()
+    }
+
+    fn rpt(&mut self, report: &Report, id: Id, parent: Option<Id>, controller: &Controller) {
+        self.batch(&[Change::Updated { report, id, parent }], controller);
+    }
+
+    fn closed(&mut self, id: Id) {
+        self.inner.closed(id);
+    }
+
+    fn batch(&mut self, changes: &[Change], controller: &Controller) {
+        let reports = changes
+            .iter()
+            .map(|x| match x {
+                Change::Updated { report, id, .. } => {
+                    let mut x = Report::clone(report);
+                    (self.f)(&mut x, *id);
+                    Some(x)
+                }
+                Change::Closed(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        let changes = changes
+            .iter()
+            .zip(&reports)
+            .map(|(x, r)| match (*x, r) {
+                (Change::Updated { id, parent, .. }, Some(report)) => {
+                    Change::Updated { report, id, parent }
+                }
+                (x, _) => x,
+            })
+            .collect::<Vec<_>>();
+
+        self.inner.batch(&changes, controller);
+    }
+
+    fn event(&mut self, event: &Event, controller: &Controller) {
+        self.inner.event(event, controller);
+    }
+
+    fn on_reset(&mut self, controller: &Controller) {
+        self.inner.on_reset(controller);
+    }
+
+    forward_hooks!();
+}
+
+// ###### THROTTLE #############################################################
+
+/// Consumer for [`ConsumeExt::throttle`].
+pub struct Throttle<C> {
+    inner: C,
+    period: Duration,
+}
+
+impl<C> Throttle<C> {
+    /// Unwrap the inner consumer.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C: Consume> Consume for Throttle<C> {
+    fn debounce(&self) -> Duration {
+        self.inner.debounce().max(self.period)
+    }
+
+    fn rpt(&mut self, report: &Report, id: Id, parent: Option<Id>, controller: &Controller) {
+        self.inner.rpt(report, id, parent, controller);
+    }
+
+    fn closed(&mut self, id: Id) {
+        self.inner.closed(id);
+    }
+
+    fn batch(&mut self, changes: &[Change], controller: &Controller) {
+        self.inner.batch(changes, controller);
+    }
+
+    fn event(&mut self, event: &Event, controller: &Controller) {
+        self.inner.event(event, controller);
+    }
+
+    fn on_reset(&mut self, controller: &Controller) {
+        self.inner.on_reset(controller);
+    }
+
+    forward_hooks!();
+}
+
+// ###### FN CONSUMER ##########################################################
+
+/// A consumer defined by a closure.
+///
+/// The closure is invoked with each [`Change`].
+///
+/// # Example
+/// ```rust
+/// use howudoin::{consumers::FnConsumer, Change};
+///
+/// howudoin::init(FnConsumer::new(|change, _| match change {
+///     Change::Updated { report, .. } => println!("{}", report.label),
+///     Change::Closed(id) => println!("closed {id}"),
+/// }));
+/// ```
+pub struct FnConsumer<F> {
+    f: F,
+    debounce: Duration,
+}
+
+impl<F> FnConsumer<F>
+where
+    F: FnMut(Change, &Controller),
+{
+    /// A consumer invoking `f` with each change, with the default debounce of 50 milliseconds.
+    pub fn new(f: F) -> Self {
+        Self {
+            f,
+            debounce: Duration::from_millis(50),
+        }
+    }
+
+    /// Set the debounce duration.
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+}
+
+impl<F> Consume for FnConsumer<F>
+where
+    F: FnMut(Change, &Controller),
+{
+    fn debounce(&self) -> Duration {
+        self.debounce
+    }
+
+    fn rpt(&mut self, report: &Report, id: Id, parent: Option<Id>, controller: &Controller) {
+        (self.f)(Change::Updated { report, id, parent }, controller);
+    }
+
+    fn batch(&mut self, changes: &[Change], controller: &Controller) {
+        for x in changes {
+            (self.f)(*x, controller);
+        }
+    }
+}
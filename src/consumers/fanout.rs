@@ -0,0 +1,443 @@
+use crate::*;
+use report::{Event, Report};
+use std::collections::BTreeMap;
+
+/// A consumer which forwards to two consumers.
+///
+/// Each consumer's [`Consume::debounce`] is honoured independently: changes are accumulated for
+/// each consumer, and delivered once its own debounce period has elapsed.
+/// The consumer loop runs at the shortest non-zero debounce, and consumers with a zero debounce
+/// are delivered the changes straight away.
+/// Events and lifecycle hooks are forwarded to both consumers immediately.
+///
+/// Tees can be nested, or use [`Fanout`] for any number of consumers.
+///
+/// # Example
+/// ```rust
+/// use howudoin::consumers::{Noop, Tee};
+/// use std::time::Duration;
+///
+/// howudoin::init(Tee::new(Noop::default(), Noop(Duration::from_secs(1))));
+/// ```
+pub struct Tee<A, B> {
+    a: Debounced<A>,
+    b: Debounced<B>,
+}
+
+impl<A: Consume, B: Consume> Tee<A, B> {
+    /// Forward to consumers `a` and `b`.
+    pub fn new(a: A, b: B) -> Self {
+        Self {
+            a: Debounced::new(a),
+            b: Debounced::new(b),
+        }
+    }
+
+    /// Unwrap the consumers.
+    pub fn into_inner(self) -> (A, B) {
+        (self.a.consumer, self.b.consumer)
+    }
+}
+
+impl<A: Consume, B: Consume> Consume for Tee<A, B> {
+    fn debounce(&self) -> Duration {
+        period([self.a.debounce, self.b.debounce])
+    }
+
+    fn rpt(
+        &mut self,
+        report: &report::Report,
+        id: Id,
+        parent: Option<Id>,
+        controller: &Controller,
+    ) {
+        self.batch(&[Change::Updated { report, id, parent }], controller);
+    }
+
+    fn closed(&mut self, id: Id) {
+        self.a.closed(id);
+        self.b.closed(id);
+    }
+
+    fn batch(&mut self, changes: &[Change], controller: &Controller) {
+        self.a.batch(changes, controller);
+        self.b.batch(changes, controller);
+    }
+
+    fn event(&mut self, event: &Event, controller: &Controller) {
+        self.a.event(event, controller);
+        self.b.event(event, controller);
+    }
+
+    fn on_start(&mut self) {
+        self.a.on_start();
+        self.b.on_start();
+    }
+
+    fn on_tick(&mut self, controller: &Controller) {
+        self.a.on_tick(controller);
+        self.b.on_tick(controller);
+    }
+
+    fn on_reset(&mut self, controller: &Controller) {
+        self.a.on_reset(controller);
+        self.b.on_reset(controller);
+    }
+
+    fn on_shutdown(&mut self, controller: &Controller) {
+        self.a.on_shutdown(controller);
+        self.b.on_shutdown(controller);
+    }
+}
+
+/// A consumer which forwards to any number of consumers.
+///
+/// This is the dynamic counterpart of [`Tee`], with each consumer's debounce honoured
+/// independently.
+///
+/// # Example
+/// ```rust
+/// use howudoin::consumers::{Fanout, Noop};
+/// use std::time::Duration;
+///
+/// let fanout = Fanout::new()
+///     .with(Noop::default())
+///     .with(Noop(Duration::from_secs(1)));
+///
+/// howudoin::init(fanout);
+/// ```
+#[derive(Default)]
+pub struct Fanout {
+    consumers: Vec<Debounced<Box<dyn Consume + Send>>>,
+}
+
+impl Fanout {
+    /// A fanout without any consumers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a consumer.
+    pub fn with<C: Consume + Send + 'static>(mut self, consumer: C) -> Self {
+        self.push(Box::new(consumer));
+        self
+    }
+
+    /// Add a boxed consumer.
+    pub fn push(&mut self, consumer: Box<dyn Consume + Send>) {
+        self.consumers.push(Debounced::new(consumer));
+    }
+
+    /// The number of consumers.
+    pub fn len(&self) -> usize {
+        self.consumers.len()
+    }
+
+    /// There are no consumers.
+    pub fn is_empty(&self) -> bool {
+        self.consumers.is_empty()
+    }
+}
+
+impl FromIterator<Box<dyn Consume + Send>> for Fanout {
+    fn from_iter<T: IntoIterator<Item = Box<dyn Consume + Send>>>(iter: T) -> Self {
+        Self {
+            consumers: iter.into_iter().map(Debounced::new).collect(),
+        }
+    }
+}
+
+impl Consume for Fanout {
+    fn debounce(&self) -> Duration {
+        if self.consumers.is_empty() {
+            Duration::from_millis(50)
+        } else {
+            period(self.consumers.iter().map(|x| x.debounce))
+        }
+    }
+
+    fn rpt(
+        &mut self,
+        report: &report::Report,
+        id: Id,
+        parent: Option<Id>,
+        controller: &Controller,
+    ) {
+        self.batch(&[Change::Updated { report, id, parent }], controller);
+    }
+
+    fn closed(&mut self, id: Id) {
+        for x in &mut self.consumers {
+            x.closed(id);
+        }
+    }
+
+    fn batch(&mut self, changes: &[Change], controller: &Controller) {
+        for x in &mut self.consumers {
+            x.batch(changes, controller);
+        }
+    }
+
+    fn event(&mut self, event: &Event, controller: &Controller) {
+        for x in &mut self.consumers {
+            x.event(event, controller);
+        }
+    }
+
+    fn on_start(&mut self) {
+        for x in &mut self.consumers {
+            x.on_start();
+        }
+    }
+
+    fn on_tick(&mut self, controller: &Controller) {
+        for x in &mut self.consumers {
+            x.on_tick(controller);
+        }
+    }
+
+    fn on_reset(&mut self, controller: &Controller) {
+        for x in &mut self.consumers {
+            x.on_reset(controller);
+        }
+    }
+
+    fn on_shutdown(&mut self, controller: &Controller) {
+        for x in &mut self.consumers {
+            x.on_shutdown(controller);
+        }
+    }
+}
+
+/// A consumer which forwards to a tuple of consumers.
+///
+/// This is converted from a tuple of consumers with [`IntoConsumer`], so a tuple can be passed
+/// straight to [`init`].
+/// Each consumer's debounce is honoured independently, as with [`Tee`].
+///
+/// # Example
+/// ```rust
+/// use howudoin::consumers::Noop;
+/// use std::time::Duration;
+///
+/// howudoin::init((Noop::default(), Noop(Duration::from_secs(1)), Noop::default()));
+/// ```
+pub struct Tuple<T>(T);
+
+macro_rules! impl_tuple {
+    ($($c:ident $i:tt),*) => {
+        impl<$($c: Consume),*> IntoConsumer for ($($c,)*) {
+            type Consumer = Tuple<($(Debounced<$c>,)*)>;
+
+            fn into_consumer(self) -> Self::Consumer {
+                Tuple(($(Debounced::new(self.$i),)*))
+            }
+        }
+
+        impl<$($c: Consume),*> Tuple<($(Debounced<$c>,)*)> {
+            /// Unwrap the consumers.
+            pub fn into_inner(self) -> ($($c,)*) {
+                ($(self.0.$i.consumer,)*)
+            }
+        }
+
+        impl<$($c: Consume),*> Consume for Tuple<($(Debounced<$c>,)*)> {
+            fn debounce(&self) -> Duration {
+                period([$(self.0.$i.debounce),*])
+            }
+
+            fn rpt(
+                &mut self,
+                report: &report::Report,
+                id: Id,
+                parent: Option<Id>,
+                controller: &Controller,
+            ) {
+                self.batch(&[Change::Updated { report, id, parent }], controller);
+            }
+
+            fn closed(&mut self, id: Id) {
+                $(self.0.$i.closed(id);)*
+            }
+
+            fn batch(&mut self, changes: &[Change], controller: &Controller) {
+                $(self.0.$i.batch(changes, controller);)*
+            }
+
+            fn event(&mut self, event: &Event, controller: &Controller) {
+                $(self.0.$i.event(event, controller);)*
+            }
+
+            fn on_start(&mut self) {
+                $(self.0.$i.on_start();)*
+            }
+
+            fn on_tick(&mut self, controller: &Controller) {
+                $(self.0.$i.on_tick(controller);)*
+            }
+
+            fn on_reset(&mut self, controller: &Controller) {
+                $(self.0.$i.on_reset(controller);)*
+            }
+
+            fn on_shutdown(&mut self, controller: &Controller) {
+                $(self.0.$i.on_shutdown(controller);)*
+            }
+        }
+    };
+}
+
+impl_tuple!(A 0, B 1);
+impl_tuple!(A 0, B 1, C 2);
+impl_tuple!(A 0, B 1, C 2, D 3);
+
+/// The consumer loop's period for consumers with the `debounces`: the shortest non-zero debounce.
+///
+/// Consumers with a zero debounce are delivered their changes straight away, so they do not need
+/// the loop to run without a deadline.
+fn period<I: IntoIterator<Item = Duration>>(debounces: I) -> Duration {
+    debounces
+        .into_iter()
+        .filter(|x| !x.is_zero())
+        .min()
+        .unwrap_or_default()
+}
+
+/// A consumer with its own debounce period and pending changes.
+///
+/// The consumer's [`Consume::debounce`] is honoured independently of the consumer loop: changes
+/// are accumulated, and delivered on the first tick after the consumer's own debounce period has
+/// elapsed.
+/// The pending changes are kept as they were received, so consumers wrapping a `Debounced` (such
+/// as a [`ConsumeExt::map_report`]) are honoured.
+/// A consumer with a zero debounce is delivered the changes straight away.
+///
+/// This is used by [`Tee`], [`Fanout`] and [`Tuple`] for each of their consumers.
+///
+/// # Example
+/// ```rust
+/// use howudoin::consumers::{Debounced, Noop};
+/// use std::time::Duration;
+///
+/// howudoin::init(Debounced::new(Noop(Duration::from_secs(1))));
+/// ```
+pub struct Debounced<C> {
+    consumer: C,
+    debounce: Duration,
+    last: Instant,
+    pending: BTreeMap<Id, Pending>,
+}
+
+/// A change accumulated by [`Debounced`].
+enum Pending {
+    Updated { report: Report, parent: Option<Id> },
+    Closed,
+}
+
+impl<C: Consume> Debounced<C> {
+    /// Wrap `consumer`, using its debounce period.
+    pub fn new(consumer: C) -> Self {
+        Self {
+            debounce: consumer.debounce(),
+            consumer,
+            last: Instant::now(),
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Unwrap the consumer.
+    pub fn into_inner(self) -> C {
+        self.consumer
+    }
+
+    /// Deliver the pending changes.
+    fn flush(&mut self, controller: &Controller) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        let pending = std::mem::take(&mut self.pending);
+        let changes = pending
+            .iter()
+            .map(|(&id, x)| match x {
+                Pending::Updated { report, parent } => Change::Updated {
+                    report,
+                    id,
+                    parent: *parent,
+                },
+                Pending::Closed => Change::Closed(id),
+            })
+            .collect::<Vec<_>>();
+
+        self.consumer.batch(&changes, controller);
+    }
+}
+
+impl<C: Consume> Consume for Debounced<C> {
+    fn debounce(&self) -> Duration {
+        self.debounce
+    }
+
+    fn rpt(
+        &mut self,
+        report: &report::Report,
+        id: Id,
+        parent: Option<Id>,
+        controller: &Controller,
+    ) {
+        self.batch(&[Change::Updated { report, id, parent }], controller);
+    }
+
+    fn closed(&mut self, id: Id) {
+        if self.debounce.is_zero() {
+            self.consumer.closed(id);
+        } else {
+            self.pending.insert(id, Pending::Closed);
+        }
+    }
+
+    fn batch(&mut self, changes: &[Change], controller: &Controller) {
+        if self.debounce.is_zero() {
+            self.consumer.batch(changes, controller);
+        } else {
+            self.pending.extend(changes.iter().map(|x| match *x {
+                Change::Updated { report, id, parent } => (
+                    id,
+                    Pending::Updated {
+                        report: report.clone(),
+                        parent,
+                    },
+                ),
+                Change::Closed(id) => (id, Pending::Closed),
+            }));
+        }
+    }
+
+    fn event(&mut self, event: &Event, controller: &Controller) {
+        self.consumer.event(event, controller);
+    }
+
+    fn on_start(&mut self) {
+        self.consumer.on_start();
+    }
+
+    /// Flush and tick the consumer if its debounce period has elapsed.
+    fn on_tick(&mut self, controller: &Controller) {
+        if self.last.elapsed() >= self.debounce {
+            self.flush(controller);
+            self.consumer.on_tick(controller);
+            self.last = Instant::now();
+        }
+    }
+
+    fn on_reset(&mut self, controller: &Controller) {
+        // deliver the closed reports before notifying the consumer
+        self.flush(controller);
+        self.consumer.on_reset(controller);
+    }
+
+    fn on_shutdown(&mut self, controller: &Controller) {
+        self.flush(controller);
+        self.consumer.on_shutdown(controller);
+    }
+}
@@ -21,10 +21,19 @@ impl Consume for JsonPrinter {
     }
 
     fn rpt(&mut self, _: &report::Report, _: Id, _: Option<Id>, controller: &Controller) {
-        let p = controller.build_progress_tree();
-        let mut stdout = std::io::stdout().lock();
-        if serde_json::to_writer(&mut stdout, &p).is_ok() {
-            writeln!(stdout).ok();
-        }
+        print(controller);
+    }
+
+    fn batch(&mut self, _: &[Change], controller: &Controller) {
+        // print the tree once for all the changes
+        print(controller);
+    }
+}
+
+fn print(controller: &Controller) {
+    let p = controller.build_progress_tree();
+    let mut stdout = std::io::stdout().lock();
+    if serde_json::to_writer(&mut stdout, &p).is_ok() {
+        writeln!(stdout).ok();
     }
 }
@@ -15,6 +15,11 @@ pub mod json_printer;
 #[cfg(feature = "json-printer")]
 pub use json_printer::JsonPrinter;
 
+mod ext;
+mod fanout;
+pub use ext::{ConsumeExt, Filter, FnConsumer, MapReport, Throttle};
+pub use fanout::{Debounced, Fanout, Tee, Tuple};
+
 /// A consumer that does not do anything.
 ///
 /// The inner duration is the debounce duration.
@@ -115,6 +115,10 @@ fn update_bar(pb: &ProgressBar, rpt: &Report) {
         State::Cancelled => {
             pb.abandon_with_message("cancelled");
         }
+
+        State::Abandoned => {
+            pb.abandon_with_message("abandoned");
+        }
     }
 
     for Message { severity, msg } in accums {
@@ -0,0 +1,221 @@
+//! Progress reporting for futures. Requires `tokio` feature.
+//!
+//! Parent scopes are tracked with a task-local, so nested reports attach to the enclosing future's
+//! report even when the task moves between threads.
+//! Streams can report the items yielded with [`ProgressStream`], and the [`io`] module
+//! has wrappers for tokio's `AsyncRead` and `AsyncWrite`.
+use super::*;
+use futures_core::Stream;
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+tokio::task_local! {
+    /// The stack of entered reports in this task, as `(instance, id)` pairs.
+    static TASK_SCOPES: Vec<(usize, Id)>;
+}
+
+/// The innermost scoped parent in the current task which belongs to the instance.
+pub(crate) fn current(key: usize) -> Option<Id> {
+    TASK_SCOPES
+        .try_with(|s| s.iter().rev().find(|(k, _)| *k == key).map(|(_, id)| *id))
+        .ok()
+        .flatten()
+}
+
+/// Poll `f` with `rpt` pushed onto the task-local scope stack.
+fn poll_scoped<T, F: FnOnce() -> T>(rpt: &Tx, f: F) -> T {
+    match rpt.instance() {
+        Some(hd) => {
+            let mut scopes = TASK_SCOPES.try_with(Vec::clone).unwrap_or_default();
+            scopes.push((hd.key(), rpt.id()));
+            TASK_SCOPES.sync_scope(scopes, f)
+        }
+        None => f(),
+    }
+}
+
+/// Extension trait for attaching progress reporting to a [`Future`]. Requires `tokio` feature.
+pub trait FutureExt: Future + Sized {
+    /// Report progress of this future.
+    ///
+    /// A report with `label` is created when the future is first polled.
+    /// Reports created with [`new`] while the future is polled are attached to the report.
+    /// The report is finished when the future completes, or cancelled if the future is dropped
+    /// before completing.
+    ///
+    /// ```rust
+    /// # async fn download() {}
+    /// use howudoin::FutureExt;
+    ///
+    /// # async fn f() {
+    /// download().with_progress("Downloading").await;
+    /// # }
+    /// ```
+    fn with_progress<L: Into<String>>(self, label: L) -> WithProgress<Self> {
+        self.with_progress_on(Howudoin::global(), label)
+    }
+
+    /// Report progress of this future, on the instance `hd`.
+    ///
+    /// See [`FutureExt::with_progress`].
+    fn with_progress_on<L: Into<String>>(self, hd: &Howudoin, label: L) -> WithProgress<Self> {
+        WithProgress {
+            fut: self,
+            hd: hd.clone(),
+            label: Some(label.into()),
+            rpt: None,
+        }
+    }
+
+    /// Poll this future with `rpt` as the parent scope of reports created with [`new`].
+    ///
+    /// This is the task-local equivalent of [`scope`].
+    fn scoped(self, rpt: &Tx) -> Scoped<Self> {
+        Scoped {
+            fut: self,
+            rpt: *rpt,
+        }
+    }
+}
+
+impl<F: Future> FutureExt for F {}
+
+pin_project! {
+    /// Future for [`FutureExt::with_progress`].
+    pub struct WithProgress<F> {
+        #[pin]
+        fut: F,
+        hd: Howudoin,
+        label: Option<String>,
+        rpt: Option<ReportGuard>,
+    }
+}
+
+impl<F: Future> Future for WithProgress<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        if let Some(label) = this.label.take() {
+            *this.rpt = Some(this.hd.new().label(label).guard().on_drop(OnDrop::Cancel));
+        }
+
+        let fut = this.fut;
+        let x = match this.rpt {
+            Some(rpt) => poll_scoped(rpt, || fut.poll(cx)),
+            None => fut.poll(cx),
+        };
+
+        if x.is_ready() {
+            if let Some(rpt) = this.rpt.take() {
+                rpt.finish();
+            }
+        }
+
+        x
+    }
+}
+
+pin_project! {
+    /// Future for [`FutureExt::scoped`].
+    pub struct Scoped<F> {
+        #[pin]
+        fut: F,
+        rpt: Tx,
+    }
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let fut = this.fut;
+        poll_scoped(this.rpt, || fut.poll(cx))
+    }
+}
+
+pin_project! {
+    /// A [`Stream`] which reports the items yielded.
+    ///
+    /// The report is finished when the stream ends, or [abandoned](OnDrop::Abandon) if the stream
+    /// is dropped beforehand.
+    /// If the report is [cancelled](Tx::cancelled), the stream ends early.
+    ///
+    /// ```rust
+    /// use howudoin::future::ProgressStream;
+    ///
+    /// # fn f(stream: impl futures_core::Stream<Item = u8>) {
+    /// let stream = ProgressStream::new(stream, howudoin::new().label("Items"));
+    /// # }
+    /// ```
+    pub struct ProgressStream<S> {
+        #[pin]
+        inner: S,
+        rpt: Option<ReportGuard>,
+    }
+}
+
+impl<S> ProgressStream<S> {
+    /// Wrap `inner`, reporting to `rpt`.
+    pub fn new(inner: S, rpt: Tx) -> Self {
+        Self {
+            inner,
+            rpt: Some(rpt.guard()),
+        }
+    }
+
+    /// Set the total number of items.
+    pub fn with_len(self, len: u64) -> Self {
+        if let Some(x) = &self.rpt {
+            Tx::clone(x).set_len(len);
+        }
+        self
+    }
+
+    /// The report, if the stream has not ended.
+    pub fn tx(&self) -> Option<&Tx> {
+        self.rpt.as_deref()
+    }
+}
+
+impl<S: Stream> Stream for ProgressStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let rpt = match this.rpt {
+            Some(x) if x.cancelled() => return Poll::Ready(None),
+            Some(x) => x,
+            None => return Poll::Ready(None),
+        };
+
+        let x = this.inner.poll_next(cx);
+        match &x {
+            Poll::Ready(Some(_)) => {
+                rpt.inc();
+            }
+            Poll::Ready(None) => {
+                if let Some(x) = this.rpt.take() {
+                    x.finish();
+                }
+            }
+            Poll::Pending => (),
+        }
+
+        x
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.rpt {
+            Some(_) => (0, self.inner.size_hint().1),
+            None => (0, Some(0)),
+        }
+    }
+}
@@ -0,0 +1,573 @@
+use super::*;
+use crate::{
+    builder::Config,
+    tx::{get_or_alloc, Token, TokenState, Tokens},
+};
+use flume::{bounded, Receiver, RecvTimeoutError, Sender};
+use parking_lot::{Mutex, RwLock};
+use std::{
+    ptr::null_mut,
+    sync::{
+        atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering::*},
+        OnceLock, Weak,
+    },
+};
+use Payload::*;
+
+/// Incremented for each consumer loop, forming the upper bits of report ids.
+pub(crate) static EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of bits of an id holding the epoch.
+///
+/// The epoch wraps around, skipping the epochs of the active loops, so at most
+/// `1 << EPOCH_BITS` loops (65536 on 64-bit targets) can be active at once.
+const EPOCH_BITS: u32 = usize::BITS / 4;
+pub(crate) const COUNTER_BITS: u32 = usize::BITS - EPOCH_BITS;
+const COUNTER_MASK: usize = !0 >> EPOCH_BITS;
+const EPOCH_MASK: usize = !0 >> COUNTER_BITS;
+
+/// The number of slots in each chunk of [`LOOPS`].
+const CHUNK: usize = 256;
+
+/// The active consumer loops' state, by epoch, for reporters to find their loop without locking.
+///
+/// The slots are allocated in chunks on first use, which are never freed.
+static LOOPS: [AtomicPtr<Slot>; (1 << EPOCH_BITS) / CHUNK] =
+    [const { AtomicPtr::new(null_mut()) }; (1 << EPOCH_BITS) / CHUNK];
+
+/// A progress reporting instance.
+///
+/// Each instance is bound to its own consumer loop and progress tree.
+/// The free functions (such as [`new`] and [`fetch`]) operate on the [`Howudoin::global`]
+/// instance, whereas an explicit instance can be used to separate progress reporting (for example,
+/// between parallel tests).
+///
+/// The instance is cheaply cloneable, with clones referencing the same consumer loop.
+///
+/// # Example
+/// ```rust
+/// let hd = howudoin::Howudoin::default();
+/// hd.init(howudoin::consumers::Noop::default());
+///
+/// let rpt = hd.new().label("Progress");
+/// rpt.inc();
+///
+/// let progress = hd.fetch();
+/// ```
+#[derive(Clone, Default)]
+pub struct Howudoin(Arc<RwLock<Option<Conn>>>);
+
+struct Conn {
+    tx: Sender<Payload>,
+    shared: Arc<Shared>,
+    /// Disconnects when the consumer loop exits.
+    done: Receiver<()>,
+    stats: Arc<Mutex<Stats>>,
+}
+
+impl Howudoin {
+    /// The global instance, which the free functions operate on.
+    pub fn global() -> &'static Howudoin {
+        static GLOBAL: OnceLock<Howudoin> = OnceLock::new();
+        GLOBAL.get_or_init(Howudoin::default)
+    }
+
+    /// Initialise the progress consumer loop for this instance.
+    ///
+    /// See [`init`].
+    pub fn init<C>(&self, consumer: C)
+    where
+        C: IntoConsumer,
+        C::Consumer: Send + 'static,
+    {
+        Builder::new().init_on(self, consumer);
+    }
+
+    /// Initialise the progress consumer loop for this instance, returning a guard which shuts
+    /// down the loop when dropped.
+    ///
+    /// See [`init_guarded`].
+    pub fn init_guarded<C>(&self, consumer: C) -> ShutdownGuard
+    where
+        C: IntoConsumer,
+        C::Consumer: Send + 'static,
+    {
+        self.init(consumer);
+        ShutdownGuard::new(self.clone())
+    }
+
+    /// Initialise the progress consumer loop for this instance as a tokio task. Requires `tokio`
+    /// feature.
+    ///
+    /// See [`init_async`].
+    #[cfg(feature = "tokio")]
+    pub fn init_async<C>(&self, consumer: C)
+    where
+        C: IntoConsumer,
+        C::Consumer: Send + 'static,
+    {
+        Builder::new().init_async_on(self, consumer);
+    }
+
+    /// Replace the consumer of the running consumer loop.
+    ///
+    /// See [`replace_consumer`].
+    pub fn replace_consumer<C>(&self, consumer: C) -> bool
+    where
+        C: IntoConsumer,
+        C::Consumer: Send + 'static,
+    {
+        self.send(|| Replace(rx::Replacement(Box::new(consumer.into_consumer()))))
+    }
+
+    /// Disable the progress reporting consumer loop, terminating the sender side.
+    ///
+    /// See [`disable`].
+    pub fn disable(&self) {
+        self.0.write().take();
+    }
+
+    /// Shutdown the progress consumer loop, waiting for it to exit.
+    ///
+    /// See [`shutdown`].
+    pub fn shutdown(&self) -> bool {
+        match self.0.write().take() {
+            Some(conn) => {
+                let done = conn.done.clone();
+                let timeout = conn.shared.cfg.shutdown_timeout;
+                // disconnects the sender side and unregisters the loop
+                drop(conn);
+                matches!(
+                    done.recv_timeout(timeout),
+                    Err(RecvTimeoutError::Disconnected)
+                )
+            }
+            None => true,
+        }
+    }
+
+    /// Generate a new progress reporter.
+    ///
+    /// See [`new`].
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(&self) -> Tx {
+        let parent = scope::current(self);
+        self.new_(|id, t| AddReport(id, parent, t))
+    }
+
+    /// Generate a new progress reporter under a parent.
+    ///
+    /// See [`new_with_parent`].
+    pub fn new_with_parent(&self, parent: Id) -> Tx {
+        self.new_(|id, t| AddReport(id, Some(parent), t))
+    }
+
+    /// Generate a new progress reporter at the root level.
+    ///
+    /// See [`new_root`].
+    pub fn new_root(&self) -> Tx {
+        self.new_(AddRootReport)
+    }
+
+    /// Generate a new progress reporter, wrapped in a [`ReportGuard`].
+    ///
+    /// See [`new_guarded`].
+    pub fn new_guarded(&self) -> ReportGuard {
+        self.new().guard()
+    }
+
+    /// Generate a new progress reporter under a parent, wrapped in a [`ReportGuard`].
+    ///
+    /// See [`new_with_parent_guarded`].
+    pub fn new_with_parent_guarded(&self, parent: Id) -> ReportGuard {
+        self.new_with_parent(parent).guard()
+    }
+
+    /// Generate a new progress reporter at the root level, wrapped in a [`ReportGuard`].
+    ///
+    /// See [`new_root_guarded`].
+    pub fn new_root_guarded(&self) -> ReportGuard {
+        self.new_root().guard()
+    }
+
+    fn new_<F: FnOnce(Id, Token) -> Payload>(&self, f: F) -> Tx {
+        self.send_with(|shared| {
+            let id = shared.next_id();
+            (f(id, shared.new_token(id)), Tx::new(id))
+        })
+        .unwrap_or_else(Tx::void)
+    }
+
+    /// Fetch the progress report tree.
+    ///
+    /// See [`fetch`].
+    pub fn fetch(&self) -> Option<Vec<report::Progress>> {
+        let (tx, rx) = bounded(1);
+        let timeout = self.send_with(|shared| (Fetch(tx), shared.cfg.fetch_timeout))?;
+        rx.recv_timeout(timeout).ok()
+    }
+
+    /// Fetch the progress report tree, without blocking the thread. Requires `tokio` feature.
+    ///
+    /// See [`fetch_async`].
+    #[cfg(feature = "tokio")]
+    pub async fn fetch_async(&self) -> Option<Vec<report::Progress>> {
+        use tokio::time::timeout;
+
+        // the lock is not held across the awaits
+        let (tx, cfg) = match &*self.0.read() {
+            Some(Conn { tx, shared, .. }) if !tx.is_disconnected() => {
+                (tx.clone(), shared.cfg.clone())
+            }
+            _ => return None,
+        };
+
+        let (reply, rx) = bounded(1);
+        timeout(cfg.fetch_timeout, tx.send_async(Fetch(reply)))
+            .await
+            .ok()?
+            .ok()?;
+        drop(tx);
+        timeout(cfg.fetch_timeout, rx.recv_async()).await.ok()?.ok()
+    }
+
+    /// Flag for cancellation.
+    ///
+    /// See [`cancel`].
+    pub fn cancel(&self) {
+        self.send_with(|shared| {
+            shared.cancelled.store(true, Relaxed);
+            shared.wake();
+            (Cancel(None), ())
+        });
+    }
+
+    /// Cancel the report with `id`, along with all of its descendants.
+    ///
+    /// See [`cancel_report`].
+    pub fn cancel_report(&self, id: Id) {
+        self.send(|| Cancel(Some(id)));
+    }
+
+    /// Check the cancellation flag.
+    ///
+    /// See [`cancelled`].
+    pub fn cancelled(&self) -> Option<bool> {
+        self.shared().map(|x| x.cancelled.load(Relaxed))
+    }
+
+    /// Wait for the cancellation flag. Requires `tokio` feature.
+    ///
+    /// See [`cancelled_async`].
+    #[cfg(feature = "tokio")]
+    pub async fn cancelled_async(&self) -> bool {
+        let Some(shared) = self.shared() else {
+            return false;
+        };
+
+        loop {
+            let notified = shared.notify.notified();
+            let mut notified = std::pin::pin!(notified);
+            // register before checking, so a notification in between is not missed
+            notified.as_mut().enable();
+
+            if !shared.is_active() {
+                return false;
+            }
+            if shared.cancelled.load(Relaxed) {
+                return true;
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Reset the progress consumer loop.
+    ///
+    /// See [`reset`].
+    pub fn reset(&self) {
+        self.send_with(|shared| {
+            shared.cancelled.store(false, Relaxed);
+            (Reset, ())
+        });
+    }
+
+    /// Subscribe to the progress events.
+    ///
+    /// See [`subscribe`].
+    pub fn subscribe(&self) -> Option<Subscription> {
+        self.subscribe_with_capacity(1024)
+    }
+
+    /// Subscribe to the progress events, buffering up to `capacity` events.
+    ///
+    /// See [`subscribe`].
+    pub fn subscribe_with_capacity(&self, capacity: usize) -> Option<Subscription> {
+        let (x, sub) = subscription::channel(capacity);
+        self.send(|| Subscribe(sub)).then_some(x)
+    }
+
+    /// The consumer loop's statistics.
+    ///
+    /// See [`stats`].
+    pub fn stats(&self) -> Option<Stats> {
+        match &*self.0.read() {
+            Some(Conn { tx, stats, .. }) if !tx.is_disconnected() => Some(stats.lock().clone()),
+            _ => None,
+        }
+    }
+
+    /// A key identifying this instance.
+    pub(crate) fn key(&self) -> usize {
+        Arc::as_ptr(&self.0) as usize
+    }
+
+    /// Access the shared state of the active consumer loop which allocated `id`.
+    pub(crate) fn with_loop<T, F: FnOnce(&Shared) -> T>(id: Id, f: F) -> Option<T> {
+        if id == 0 {
+            // reporters without a consumer loop
+            return None;
+        }
+
+        let epoch = id >> COUNTER_BITS;
+        let chunk = LOOPS[epoch / CHUNK].load(Acquire);
+        if chunk.is_null() {
+            return None;
+        }
+
+        // SAFETY: an allocated chunk holds `CHUNK` slots and is never freed
+        let slot = unsafe { &*chunk.add(epoch % CHUNK) };
+        slot.with(f)
+    }
+
+    pub(crate) fn set_tx(
+        &self,
+        tx: Sender<Payload>,
+        done: Receiver<()>,
+        stats: Arc<Mutex<Stats>>,
+        cfg: Config,
+    ) {
+        let mut tries = 0;
+        let shared = loop {
+            assert!(tries <= EPOCH_MASK, "too many active consumer loops");
+            tries += 1;
+
+            let shared = Arc::new(Shared {
+                cancelled: AtomicBool::new(false),
+                epoch: EPOCH.fetch_add(1, Relaxed) & EPOCH_MASK,
+                // start at 1, since 0 is used by reporters without a consumer loop
+                next_id: AtomicUsize::new(1),
+                tokens: Default::default(),
+                instance: Arc::downgrade(&self.0),
+                cfg: cfg.clone(),
+                #[cfg(feature = "tokio")]
+                notify: tokio::sync::Notify::new(),
+            });
+
+            // the epoch wraps around, skip the epochs of the active loops
+            if shared.slot().try_insert(&shared) {
+                break shared;
+            }
+        };
+
+        let conn = Conn {
+            tx,
+            shared,
+            done,
+            stats,
+        };
+
+        // the replaced connection unregisters its loop when dropped
+        self.0.write().replace(conn);
+    }
+
+    /// Send a payload, returning if it was sent.
+    pub(crate) fn send<F: FnOnce() -> Payload>(&self, payload: F) -> bool {
+        self.send_with(|_| (payload(), ())).is_some()
+    }
+
+    /// Send a payload built with access to the loop's shared state.
+    ///
+    /// Returns `None` if there is no connected consumer loop, or the payload was not sent.
+    fn send_with<T, F>(&self, f: F) -> Option<T>
+    where
+        F: FnOnce(&Arc<Shared>) -> (Payload, T),
+    {
+        match &*self.0.read() {
+            Some(Conn { tx, shared, .. }) if !tx.is_disconnected() => {
+                let (payload, x) = f(shared);
+                shared.cfg.send(tx, payload).then_some(x)
+            }
+            _ => None,
+        }
+    }
+
+    fn shared(&self) -> Option<Arc<Shared>> {
+        match &*self.0.read() {
+            Some(Conn { tx, shared, .. }) if !tx.is_disconnected() => Some(shared.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// State shared between the producers and a consumer loop.
+#[derive(Debug)]
+pub(crate) struct Shared {
+    /// The loop has been flagged for cancellation.
+    cancelled: AtomicBool,
+    /// The loop's epoch, distinguishing ids between loops.
+    epoch: usize,
+    /// The report id counter.
+    next_id: AtomicUsize,
+    /// The tokens of the loop's reports, indexed by the id's counter.
+    tokens: Arc<Tokens>,
+    /// The instance the loop is connected to.
+    instance: Weak<RwLock<Option<Conn>>>,
+    /// The channel configuration.
+    cfg: Config,
+    /// Wakes the [`Howudoin::cancelled_async`] waiters.
+    #[cfg(feature = "tokio")]
+    notify: tokio::sync::Notify,
+}
+
+impl Shared {
+    /// Allocate a report id, combining the epoch with an incrementing counter.
+    fn next_id(&self) -> Id {
+        let counter = self.next_id.fetch_add(1, Relaxed) & COUNTER_MASK;
+        self.epoch << COUNTER_BITS | counter
+    }
+
+    /// Allocate the token of a new report.
+    fn new_token(&self, id: Id) -> Token {
+        let index = id & COUNTER_MASK;
+        // allocated up front, so the reporter can look the token up straight away
+        self.tokens.get_or_alloc(index);
+        Token::new(self.tokens.clone(), index)
+    }
+
+    /// The token of the report with `id`, if the report belongs to this loop.
+    pub(crate) fn token(&self, id: Id) -> Option<&TokenState> {
+        if id == 0 || id >> COUNTER_BITS != self.epoch {
+            return None;
+        }
+
+        self.tokens.get(id & COUNTER_MASK)
+    }
+
+    /// The loop is flagged for cancellation.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Relaxed)
+    }
+
+    /// The instance the loop is connected to.
+    pub(crate) fn instance(&self) -> Option<Howudoin> {
+        self.instance.upgrade().map(Howudoin)
+    }
+
+    /// The loop is the one receiving reports.
+    #[cfg(feature = "tokio")]
+    fn is_active(&self) -> bool {
+        std::ptr::eq(self.slot().shared.load(SeqCst), self)
+    }
+
+    /// Wake the tasks waiting for cancellation.
+    fn wake(&self) {
+        #[cfg(feature = "tokio")]
+        self.notify.notify_waiters();
+    }
+
+    /// The slot the loop is registered in.
+    fn slot(&self) -> &'static Slot {
+        let chunk = get_or_alloc(&LOOPS[self.epoch / CHUNK], CHUNK);
+        // SAFETY: an allocated chunk holds `CHUNK` slots and is never freed
+        unsafe { &*chunk.add(self.epoch % CHUNK) }
+    }
+}
+
+impl Drop for Conn {
+    fn drop(&mut self) {
+        // the loop is no longer the one receiving reports
+        self.shared.slot().remove(&self.shared);
+        self.shared.wake();
+    }
+}
+
+/// A consumer loop's [`Shared`] state, which reporters access without locking.
+///
+/// Readers pin the slot while they access the state, and removing the state waits for the
+/// readers which could still be accessing it before dropping it.
+#[derive(Default)]
+pub(crate) struct Slot {
+    shared: AtomicPtr<Shared>,
+    /// The number of pinning readers, alternating between two counters so that new readers do
+    /// not hold up a removal.
+    pins: [AtomicUsize; 2],
+    phase: AtomicUsize,
+}
+
+impl Slot {
+    /// Access the state, returning `None` if the slot is empty.
+    fn with<T, F: FnOnce(&Shared) -> T>(&self, f: F) -> Option<T> {
+        let pin = Pin::new(&self.pins[self.phase.load(SeqCst) & 1]);
+        let shared = self.shared.load(SeqCst);
+        // SAFETY: the state is not dropped while a reader which could have loaded it is pinned
+        let x = unsafe { shared.as_ref() }.map(f);
+        drop(pin);
+        x
+    }
+
+    /// Register the state if the slot is empty, returning if it was registered.
+    fn try_insert(&self, shared: &Arc<Shared>) -> bool {
+        let ptr = Arc::into_raw(Arc::clone(shared)).cast_mut();
+        let inserted = self
+            .shared
+            .compare_exchange(null_mut(), ptr, SeqCst, SeqCst)
+            .is_ok();
+        if !inserted {
+            // SAFETY: the pointer came from `Arc::into_raw`, and was never shared
+            drop(unsafe { Arc::from_raw(ptr) });
+        }
+        inserted
+    }
+
+    /// Unregister the state, if it is still registered.
+    fn remove(&self, shared: &Arc<Shared>) {
+        let ptr = Arc::as_ptr(shared).cast_mut();
+        if self
+            .shared
+            .compare_exchange(ptr, null_mut(), SeqCst, SeqCst)
+            .is_ok()
+        {
+            self.unpin_all();
+            // SAFETY: the pointer came from `Arc::into_raw`, and no reader can access it
+            drop(unsafe { Arc::from_raw(ptr) });
+        }
+    }
+
+    /// Wait for the readers which could have loaded a replaced state.
+    fn unpin_all(&self) {
+        // a reader pinning either counter after it is drained will load the new state
+        for _ in 0..2 {
+            let pins = &self.pins[self.phase.fetch_add(1, SeqCst) & 1];
+            while pins.load(SeqCst) != 0 {
+                std::thread::yield_now();
+            }
+        }
+    }
+}
+
+/// A reader of a [`Slot`], which unpins when dropped.
+struct Pin<'a>(&'a AtomicUsize);
+
+impl<'a> Pin<'a> {
+    fn new(pins: &'a AtomicUsize) -> Self {
+        pins.fetch_add(1, SeqCst);
+        Self(pins)
+    }
+}
+
+impl Drop for Pin<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Release);
+    }
+}
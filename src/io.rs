@@ -0,0 +1,362 @@
+//! Progress reporting for [`std::io`] streams.
+//!
+//! The wrappers format the report position as bytes, increment the position with each read or
+//! write, and take an optional total length.
+//!
+//! ```rust
+//! use howudoin::io::ProgressReader;
+//! use std::io::Read;
+//!
+//! let data = vec![0u8; 1024];
+//! let rpt = howudoin::new().label("Reading");
+//! let mut reader = ProgressReader::new(data.as_slice(), rpt).with_len(1024);
+//!
+//! let mut buf = Vec::new();
+//! reader.read_to_end(&mut buf).unwrap(); // report is finished on EOF
+//! ```
+use super::*;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "tokio")]
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Copy the entire contents of `reader` into `writer`, reporting progress to `rpt`.
+///
+/// The report is finished once `reader` reaches EOF.
+/// Returns the number of bytes copied, see [`std::io::copy`].
+///
+/// ```rust
+/// let data = vec![0u8; 1024];
+/// let rpt = howudoin::new().label("Copying").set_len(1024);
+///
+/// let mut out = Vec::new();
+/// howudoin::io::copy_with_progress(&mut data.as_slice(), &mut out, rpt).unwrap();
+/// ```
+pub fn copy_with_progress<R, W>(reader: &mut R, writer: &mut W, rpt: Tx) -> io::Result<u64>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+{
+    io::copy(&mut ProgressReader::new(reader, rpt), writer)
+}
+
+// ###### READER ###############################################################
+
+/// A reader which reports the bytes read.
+///
+/// The report is finished when the reader reaches EOF, or [abandoned](OnDrop::Abandon) if the
+/// reader is dropped beforehand.
+/// If the inner reader implements [`BufRead`], so does the `ProgressReader`, reporting the bytes
+/// consumed.
+pub struct ProgressReader<R> {
+    inner: R,
+    rpt: Option<ReportGuard>,
+}
+
+impl<R> ProgressReader<R> {
+    /// Wrap `inner`, reporting to `rpt`.
+    pub fn new(inner: R, rpt: Tx) -> Self {
+        Self {
+            inner,
+            rpt: Some(rpt.fmt_as_bytes(true).guard()),
+        }
+    }
+
+    /// Set the total length, in bytes.
+    ///
+    /// For files, this can be taken from the metadata.
+    pub fn with_len(self, len: u64) -> Self {
+        if let Some(x) = &self.rpt {
+            Tx::clone(x).set_len(len);
+        }
+        self
+    }
+
+    /// The report, if the reader has not reached EOF.
+    pub fn tx(&self) -> Option<&Tx> {
+        self.rpt.as_deref()
+    }
+
+    /// Get a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Unwrap the inner reader.
+    ///
+    /// The report is finalised as if the reader was dropped.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn progress(&mut self, n: usize, eof: bool) {
+        if eof {
+            if let Some(x) = self.rpt.take() {
+                x.finish();
+            }
+        } else if let Some(x) = &self.rpt {
+            x.inc_by(n as u64);
+        }
+    }
+}
+
+impl<R: Seek> ProgressReader<R> {
+    /// Set the total length to the bytes remaining between the current position and the end of
+    /// the stream.
+    pub fn with_seek_len(mut self) -> io::Result<Self> {
+        let pos = self.inner.stream_position()?;
+        let end = self.inner.seek(SeekFrom::End(0))?;
+        self.inner.seek(SeekFrom::Start(pos))?;
+        Ok(self.with_len(end.saturating_sub(pos)))
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.progress(n, n == 0 && !buf.is_empty());
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for ProgressReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let eof = self.inner.fill_buf()?.is_empty();
+        if eof {
+            self.progress(0, true);
+        }
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.progress(amt, false);
+    }
+}
+
+// ###### WRITER ###############################################################
+
+/// A writer which reports the bytes written.
+///
+/// Since a writer has no EOF, the report should be finished with [`ProgressWriter::finish`];
+/// otherwise it is [abandoned](OnDrop::Abandon) when the writer is dropped.
+pub struct ProgressWriter<W> {
+    inner: W,
+    rpt: ReportGuard,
+}
+
+impl<W> ProgressWriter<W> {
+    /// Wrap `inner`, reporting to `rpt`.
+    pub fn new(inner: W, rpt: Tx) -> Self {
+        Self {
+            inner,
+            rpt: rpt.fmt_as_bytes(true).guard(),
+        }
+    }
+
+    /// Set the total length, in bytes.
+    pub fn with_len(self, len: u64) -> Self {
+        Tx::clone(&self.rpt).set_len(len);
+        self
+    }
+
+    /// The report.
+    pub fn tx(&self) -> &Tx {
+        &self.rpt
+    }
+
+    /// Get a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Finish the report and unwrap the inner writer.
+    pub fn finish(self) -> W {
+        self.rpt.finish();
+        self.inner
+    }
+}
+
+impl<W: Write> Write for ProgressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.rpt.inc_by(n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// ###### ASYNC ################################################################
+
+#[cfg(feature = "tokio")]
+fn interrupted() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "progress report was cancelled")
+}
+
+#[cfg(feature = "tokio")]
+pin_project_lite::pin_project! {
+    /// An [`AsyncRead`] which reports the bytes read. Requires `tokio` feature.
+    ///
+    /// The report is finished when the reader reaches EOF, or [abandoned](OnDrop::Abandon) if the
+    /// reader is dropped beforehand.
+    /// If the report is [cancelled](Tx::cancelled), reading returns an
+    /// [`io::ErrorKind::Interrupted`] error.
+    pub struct AsyncProgressReader<R> {
+        #[pin]
+        inner: R,
+        rpt: Option<ReportGuard>,
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R> AsyncProgressReader<R> {
+    /// Wrap `inner`, reporting to `rpt`.
+    pub fn new(inner: R, rpt: Tx) -> Self {
+        Self {
+            inner,
+            rpt: Some(rpt.fmt_as_bytes(true).guard()),
+        }
+    }
+
+    /// Set the total length, in bytes.
+    pub fn with_len(self, len: u64) -> Self {
+        if let Some(x) = &self.rpt {
+            Tx::clone(x).set_len(len);
+        }
+        self
+    }
+
+    /// The report, if the reader has not reached EOF.
+    pub fn tx(&self) -> Option<&Tx> {
+        self.rpt.as_deref()
+    }
+
+    /// Unwrap the inner reader.
+    ///
+    /// The report is finalised as if the reader was dropped.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncRead> AsyncRead for AsyncProgressReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.project();
+
+        if this.rpt.as_ref().map(|x| x.cancelled()).unwrap_or_default() {
+            return Poll::Ready(Err(interrupted()));
+        }
+
+        let filled = buf.filled().len();
+        let x = this.inner.poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = &x {
+            let n = buf.filled().len() - filled;
+            if n == 0 && buf.remaining() > 0 {
+                if let Some(x) = this.rpt.take() {
+                    x.finish();
+                }
+            } else if let Some(x) = this.rpt {
+                x.inc_by(n as u64);
+            }
+        }
+
+        x
+    }
+}
+
+#[cfg(feature = "tokio")]
+pin_project_lite::pin_project! {
+    /// An [`AsyncWrite`] which reports the bytes written. Requires `tokio` feature.
+    ///
+    /// The report is finished when the writer is shutdown, or [abandoned](OnDrop::Abandon) if the
+    /// writer is dropped beforehand.
+    /// If the report is [cancelled](Tx::cancelled), writing returns an
+    /// [`io::ErrorKind::Interrupted`] error.
+    pub struct AsyncProgressWriter<W> {
+        #[pin]
+        inner: W,
+        rpt: Option<ReportGuard>,
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W> AsyncProgressWriter<W> {
+    /// Wrap `inner`, reporting to `rpt`.
+    pub fn new(inner: W, rpt: Tx) -> Self {
+        Self {
+            inner,
+            rpt: Some(rpt.fmt_as_bytes(true).guard()),
+        }
+    }
+
+    /// Set the total length, in bytes.
+    pub fn with_len(self, len: u64) -> Self {
+        if let Some(x) = &self.rpt {
+            Tx::clone(x).set_len(len);
+        }
+        self
+    }
+
+    /// The report, if the writer has not been shutdown.
+    pub fn tx(&self) -> Option<&Tx> {
+        self.rpt.as_deref()
+    }
+
+    /// Unwrap the inner writer.
+    ///
+    /// The report is finalised as if the writer was dropped.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: AsyncWrite> AsyncWrite for AsyncProgressWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.project();
+
+        if this.rpt.as_ref().map(|x| x.cancelled()).unwrap_or_default() {
+            return Poll::Ready(Err(interrupted()));
+        }
+
+        let x = this.inner.poll_write(cx, buf);
+        if let (Poll::Ready(Ok(n)), Some(rpt)) = (&x, this.rpt) {
+            rpt.inc_by(*n as u64);
+        }
+
+        x
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.project();
+        let x = this.inner.poll_shutdown(cx);
+        if let Poll::Ready(Ok(())) = &x {
+            if let Some(x) = this.rpt.take() {
+                x.finish();
+            }
+        }
+
+        x
+    }
+}
@@ -0,0 +1,139 @@
+//! Progress reporting for iterators.
+use super::*;
+
+/// Extension trait for driving a report from an [`Iterator`].
+pub trait ProgressIteratorExt: Iterator + Sized {
+    /// Report progress of this iterator.
+    ///
+    /// A report with `label` is created when the iterator is first advanced.
+    /// The length is taken from the [`Iterator::size_hint`] if it is exact, the position is
+    /// incremented for each item, and the report is finished when the iterator is exhausted.
+    /// If the iterator is dropped before being exhausted, the report is
+    /// [abandoned](OnDrop::Abandon).
+    ///
+    /// ```rust
+    /// use howudoin::ProgressIteratorExt;
+    ///
+    /// for _ in (0..100).progress("Processing") {
+    ///     // ...
+    /// }
+    /// ```
+    fn progress<L: Into<String>>(self, label: L) -> ProgressIter<Self> {
+        self.progress_on(Howudoin::global(), label)
+    }
+
+    /// Report progress of this iterator, on the instance `hd`.
+    ///
+    /// See [`ProgressIteratorExt::progress`].
+    fn progress_on<L: Into<String>>(self, hd: &Howudoin, label: L) -> ProgressIter<Self> {
+        ProgressIter {
+            iter: self,
+            hd: hd.clone(),
+            label: Some(label.into()),
+            parent: None,
+            bytes: false,
+            cancellable: false,
+            on_drop: OnDrop::default(),
+            rpt: None,
+        }
+    }
+}
+
+impl<I: Iterator> ProgressIteratorExt for I {}
+
+/// Iterator for [`ProgressIteratorExt::progress`].
+pub struct ProgressIter<I> {
+    iter: I,
+    hd: Howudoin,
+    label: Option<String>,
+    parent: Option<Id>,
+    bytes: bool,
+    cancellable: bool,
+    on_drop: OnDrop,
+    rpt: Option<ReportGuard>,
+}
+
+impl<I> ProgressIter<I> {
+    /// Create the report under `parent`, rather than using [`new`]'s parenting.
+    pub fn with_parent(mut self, parent: Id) -> Self {
+        self.parent = Some(parent);
+        self
+    }
+
+    /// Flag to format the position as bytes.
+    pub fn fmt_as_bytes(mut self, fmt_as_bytes: bool) -> Self {
+        self.bytes = fmt_as_bytes;
+        self
+    }
+
+    /// Stop iterating early if the report is [cancelled](Tx::cancelled).
+    pub fn cancellable(mut self, cancellable: bool) -> Self {
+        self.cancellable = cancellable;
+        self
+    }
+
+    /// Set the action to take if the iterator is dropped before being exhausted.
+    pub fn on_drop(mut self, on_drop: OnDrop) -> Self {
+        self.on_drop = on_drop;
+        self
+    }
+
+    /// The report, if the iterator has been advanced.
+    pub fn tx(&self) -> Option<&Tx> {
+        self.rpt.as_deref()
+    }
+}
+
+impl<I: Iterator> Iterator for ProgressIter<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(label) = self.label.take() {
+            let len = match self.iter.size_hint() {
+                (lo, Some(hi)) if lo == hi => Some(lo as u64),
+                _ => None,
+            };
+            let rpt = match self.parent {
+                Some(parent) => self.hd.new_with_parent(parent),
+                None => self.hd.new(),
+            };
+            self.rpt = Some(
+                rpt.label(label)
+                    .set_len(len)
+                    .fmt_as_bytes(self.bytes)
+                    .guard()
+                    .on_drop(self.on_drop),
+            );
+        }
+
+        let rpt = self.rpt.as_ref()?;
+
+        if self.cancellable && rpt.cancelled() {
+            return None;
+        }
+
+        match self.iter.next() {
+            Some(x) => {
+                rpt.inc();
+                Some(x)
+            }
+            None => {
+                if let Some(rpt) = self.rpt.take() {
+                    rpt.finish();
+                }
+                None
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.label.is_none() && self.rpt.is_none() {
+            return (0, Some(0)); // exhausted
+        }
+
+        match self.iter.size_hint() {
+            (_, hi) if self.cancellable => (0, hi),
+            x => x,
+        }
+    }
+}
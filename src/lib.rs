@@ -57,6 +57,38 @@
 //! rpt.close();  // close a report from display
 //! ```
 //!
+//! Iterators can drive a report with [`ProgressIteratorExt::progress`].
+//!
+//! ```rust
+//! use howudoin::ProgressIteratorExt;
+//!
+//! for _ in (0..100).progress("Processing") {
+//!     // ...
+//! }
+//! ```
+//!
+//! Reports created with [`new`] are parented under the report of the innermost [`scope`] on the
+//! current thread, falling back to the _last_ report generated.
+//!
+//! ```rust
+//! let parent = howudoin::new_root().label("Parent");
+//! howudoin::scope(&parent, || {
+//!     let child = howudoin::new().label("Child"); // attached to parent
+//! });
+//! ```
+//!
+//! Reports can also be wrapped in a [`ReportGuard`], which marks the report as abandoned if it is
+//! dropped before being finished (for example, on an early return).
+//!
+//! ```rust
+//! fn work() -> Result<(), ()> {
+//!     let rpt = howudoin::new_guarded().label("Work");
+//!     Err(())?; // report is marked as abandoned
+//!     rpt.finish();
+//!     Ok(())
+//! }
+//! ```
+//!
 //! ## Progress Display
 //!
 //! Progress display is abstracted from the producer.
@@ -67,9 +99,18 @@
 //!
 //! ```rust
 //! // initialise a term-line consumer
+//! # #[cfg(feature = "term-line")]
 //! howudoin::init(howudoin::consumers::TermLine::default());
 //! ```
 //!
+//! Before exiting, the consumer loop should be [`shutdown`], which delivers any pending changes
+//! to the consumer. [`init_guarded`] returns a guard which does this when dropped.
+//!
+//! ```rust
+//! let _guard = howudoin::init_guarded(howudoin::consumers::Noop::default());
+//! howudoin::new().label("Progress").finish(); // displayed on exit
+//! ```
+//!
 //! ## Progress Consumption
 //!
 //! Progress reports can also be _requested_ from the consumer loop.
@@ -84,41 +125,88 @@
 //! let progress = howudoin::fetch();
 //! ```
 //!
+//! Live updates can be pulled as [`report::Event`]s with a [`subscribe`]d [`Subscription`],
+//! alongside the consumer.
+//!
 //! ## Opt-in
 //!
 //! Progress reports are only sent to a consumer if the consumer loop has been initialised.
 //! In situations where the loop has not been initialised, progress reporting is a very cheap void
 //! operation.
 //! This means producers can be neatly separated from consumers.
+//!
+//! ## Instances
+//!
+//! The free functions operate on a global instance.
+//! Separate consumer loops, each with their own progress tree, can be created with a
+//! [`Howudoin`] instance, which has the same API as the free functions.
+//!
+//! ```rust
+//! let hd = howudoin::Howudoin::default();
+//! hd.init(howudoin::consumers::Noop::default());
+//!
+//! let rpt = hd.new().label("Progress");
+//! let progress = hd.fetch();
+//! ```
 #![warn(missing_docs)]
 
 use flume::Sender;
 use report::Progress;
-use std::time::{Duration, Instant};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+mod builder;
 pub mod consumers;
 pub mod flat_tree;
+#[cfg(feature = "tokio")]
+pub mod future;
+mod instance;
+pub mod io;
+pub mod iter;
+#[cfg(feature = "rayon")]
+pub mod par;
 pub mod report;
 mod rx;
+mod scope;
+mod subscription;
 #[cfg(test)]
 mod tests;
 mod tx;
 
 /// A report identifier.
 ///
-/// For a consumer instantiation, identifiers are distinct, increasing counters.
-/// Note that the counter will wrap around.
+/// Identifiers are allocated by the producer without blocking.
+/// For a consumer instantiation, identifiers are distinct, increasing counters, combined with an
+/// epoch which distinguishes identifiers between consumer instantiations.
+/// Note that the counter will wrap around, as will the epoch once the consumer instantiation is
+/// disabled.
 pub type Id = usize;
 
-pub use rx::Controller;
-pub use tx::{cancel, cancelled, disable, fetch, init, new, new_root, new_with_parent, reset, Tx};
+pub use builder::{Backpressure, Builder, Handle, ShutdownGuard};
+pub use consumers::ConsumeExt;
+#[cfg(feature = "tokio")]
+pub use future::FutureExt;
+pub use instance::Howudoin;
+pub use iter::ProgressIteratorExt;
+pub use rx::{Controller, Pump, Stats};
+pub use scope::{scope, Scope};
+pub use subscription::Subscription;
+pub use tx::{
+    cancel, cancel_report, cancelled, disable, fetch, init, init_guarded, new, new_guarded,
+    new_root, new_root_guarded, new_with_parent, new_with_parent_guarded, replace_consumer, reset,
+    shutdown, stats, subscribe, OnDrop, ReportGuard, Tx,
+};
+#[cfg(feature = "tokio")]
+pub use tx::{cancelled_async, fetch_async, init_async};
 
 #[derive(Debug)]
 enum Payload {
     /// Add a new reporter, optionally under the parent.
-    AddReport(Option<Id>, Sender<Id>),
+    AddReport(Id, Option<Id>, tx::Token),
     /// Add a new root report.
-    AddRootReport(Sender<Id>),
+    AddRootReport(Id, tx::Token),
     /// Fetch the progress history.
     Fetch(Sender<Vec<report::Progress>>),
     /// Set the label.
@@ -129,22 +217,67 @@ enum Payload {
     SetLen(Id, Option<u64>),
     /// Set whether to format the length and position as bytes.
     SetFmtBytes(Id, bool),
-    /// Increment the progress position by a number of ticks.
-    Inc(Id, u64),
-    /// Set the progress position.
-    SetPos(Id, u64),
+    /// A progress position has changed.
+    ///
+    /// The positions are sampled from the reports' tokens on the next debounce tick.
+    PosChanged,
     /// Add an accumulation message.
     Accum(Id, report::Severity, String),
     /// Reporter has finished, but should be kept displayed.
     Finish(Id),
     /// Reporter has finished and should be removed from display.
     Close(Id),
+    /// Reporter was dropped before being finished.
+    Abandon(Id),
     /// Set cancellation flag to true.
-    Cancel,
-    /// Get the cancellation status.
-    Cancelled(Sender<bool>),
+    ///
+    /// If an id is specified, only that report (and its descendants) are cancelled.
+    Cancel(Option<Id>),
     /// Reset the controller's state.
     Reset,
+    /// Add a subscriber of events.
+    Subscribe(subscription::Subscriber),
+    /// Replace the consumer.
+    Replace(rx::Replacement),
+}
+
+impl Payload {
+    /// The payload creates a report.
+    fn is_creation(&self) -> bool {
+        matches!(self, Self::AddReport(..) | Self::AddRootReport(..))
+    }
+
+    /// The payload must not be dropped, regardless of backpressure.
+    ///
+    /// Cancelling and resetting update the producer side state before sending, so dropping them
+    /// would leave it out of sync with the consumer loop.
+    fn is_control(&self) -> bool {
+        matches!(
+            self,
+            Self::Cancel(_) | Self::Reset | Self::Subscribe(_) | Self::Replace(_)
+        )
+    }
+
+    /// The payload can be dropped without losing the update.
+    fn is_coalescible(&self) -> bool {
+        matches!(self, Self::PosChanged)
+    }
+}
+
+/// A change to a report, delivered to [`Consume::batch`].
+#[derive(Debug, Copy, Clone)]
+pub enum Change<'a> {
+    /// The report was added or updated, see [`Consume::rpt`].
+    Updated {
+        /// The updated report.
+        report: &'a report::Report,
+        /// The report's identifier.
+        id: Id,
+        /// The report's parent.
+        parent: Option<Id>,
+    },
+    /// The report was closed, see [`Consume::closed`].
+    Closed(Id),
 }
 
 /// A report consumer.
@@ -182,4 +315,123 @@ pub trait Consume {
     ///
     /// The default implementation is to do nothing.
     fn closed(&mut self, _id: Id) {}
+
+    /// Invoked with all the changes since the last debounce.
+    ///
+    /// The changes are ordered by report id, and `batch` is only invoked if there are changes.
+    /// The default implementation forwards each change to [`Consume::rpt`] or [`Consume::closed`].
+    /// Consumers which render the whole tree can implement this to render once per debounce.
+    fn batch(&mut self, changes: &[Change], controller: &Controller) {
+        for x in changes {
+            match *x {
+                Change::Updated { report, id, parent } => self.rpt(report, id, parent, controller),
+                Change::Closed(id) => self.closed(id),
+            }
+        }
+    }
+
+    /// Invoked with each [`Event`](report::Event) as the consumer loop processes updates.
+    ///
+    /// Unlike [`Consume::rpt`], events are not debounced, allowing consumers such as loggers to
+    /// react to each change.
+    /// The default implementation is to do nothing.
+    fn event(&mut self, _event: &report::Event, _controller: &Controller) {}
+
+    /// Invoked once when the consumer loop starts, on the loop's thread.
+    ///
+    /// This can be used to set up resources, such as terminal modes.
+    /// The default implementation is to do nothing.
+    fn on_start(&mut self) {}
+
+    /// Invoked every [`Consume::debounce`] period, after any changes have been delivered.
+    ///
+    /// Unlike [`Consume::rpt`], this is invoked even if there have been no changes, which is useful
+    /// for animations and stall detection. With a zero debounce, it is invoked after each update.
+    /// The default implementation is to do nothing.
+    fn on_tick(&mut self, _controller: &Controller) {}
+
+    /// Invoked when the consumer loop is [`reset`].
+    ///
+    /// The reports removed by the reset are delivered to [`Consume::closed`] beforehand.
+    /// The default implementation is to do nothing.
+    fn on_reset(&mut self, _controller: &Controller) {}
+
+    /// Invoked once when the consumer loop exits, after the final changes have been delivered.
+    ///
+    /// The loop exits on [`shutdown`] or [`disable`], or when it is replaced by another [`init`].
+    /// This is also invoked on a consumer replaced with [`replace_consumer`].
+    /// The default implementation is to do nothing.
+    fn on_shutdown(&mut self, _controller: &Controller) {}
+}
+
+impl<C: Consume + ?Sized> Consume for Box<C> {
+    fn debounce(&self) -> Duration {
+        (**self).debounce()
+    }
+
+    fn rpt(
+        &mut self,
+        report: &report::Report,
+        id: Id,
+        parent: Option<Id>,
+        controller: &Controller,
+    ) {
+        (**self).rpt(report, id, parent, controller)
+    }
+
+    fn closed(&mut self, id: Id) {
+        (**self).closed(id)
+    }
+
+    fn batch(&mut self, changes: &[Change], controller: &Controller) {
+        (**self).batch(changes, controller)
+    }
+
+    fn event(&mut self, event: &report::Event, controller: &Controller) {
+        (**self).event(event, controller)
+    }
+
+    fn on_start(&mut self) {
+        (**self).on_start()
+    }
+
+    fn on_tick(&mut self, controller: &Controller) {
+        (**self).on_tick(controller)
+    }
+
+    fn on_reset(&mut self, controller: &Controller) {
+        (**self).on_reset(controller)
+    }
+
+    fn on_shutdown(&mut self, controller: &Controller) {
+        (**self).on_shutdown(controller)
+    }
+}
+
+/// Conversion into a [`Consume`]r, accepted when initialising progress reporting.
+///
+/// This is implemented for all consumers, and for tuples of up to four consumers, which are
+/// converted into a [`consumers::Tuple`] honouring each consumer's debounce independently.
+///
+/// # Example
+/// ```rust
+/// use howudoin::consumers::Noop;
+/// use std::time::Duration;
+///
+/// howudoin::init((Noop::default(), Noop(Duration::from_secs(1))));
+/// ```
+pub trait IntoConsumer {
+    /// The consumer which is converted into.
+    type Consumer: Consume;
+
+    /// Convert into the consumer.
+    fn into_consumer(self) -> Self::Consumer;
+}
+
+impl<C: Consume> IntoConsumer for C {
+    type Consumer = C;
+
+    fn into_consumer(self) -> C {
+        self
+    }
 }
@@ -0,0 +1,211 @@
+//! Progress reporting for rayon parallel iterators. Requires `rayon` feature.
+//!
+//! Each rayon job accumulates its increments locally and sends them in batches, avoiding a
+//! message per item.
+use super::*;
+use parking_lot::Mutex;
+use rayon::iter::{plumbing::UnindexedConsumer, ParallelIterator};
+
+/// The number of items a job accumulates before sending the increment.
+const BATCH: u64 = 64;
+
+/// Extension trait for driving a report from a [`ParallelIterator`]. Requires `rayon` feature.
+pub trait ParallelProgressExt: ParallelIterator {
+    /// Report progress of this parallel iterator.
+    ///
+    /// A report with `label` is created when the iterator is driven.
+    /// The length is taken from the iterator if it is known, the position is incremented for each
+    /// item, and the report is finished when the iterator completes.
+    ///
+    /// ```rust
+    /// use howudoin::par::ParallelProgressExt;
+    /// use rayon::prelude::*;
+    ///
+    /// let sum: u64 = (0..1000_u64).into_par_iter().progress("Summing").sum();
+    /// ```
+    fn progress<L: Into<String>>(self, label: L) -> ProgressParIter<Self> {
+        self.progress_on(Howudoin::global(), label)
+    }
+
+    /// Report progress of this parallel iterator, on the instance `hd`.
+    ///
+    /// See [`ParallelProgressExt::progress`].
+    fn progress_on<L: Into<String>>(self, hd: &Howudoin, label: L) -> ProgressParIter<Self> {
+        ProgressParIter {
+            iter: self,
+            hd: hd.clone(),
+            label: label.into(),
+            parent: None,
+            workers: false,
+            cancellable: false,
+        }
+    }
+}
+
+impl<I: ParallelIterator> ParallelProgressExt for I {}
+
+/// Parallel iterator for [`ParallelProgressExt::progress`].
+pub struct ProgressParIter<I> {
+    iter: I,
+    hd: Howudoin,
+    label: String,
+    parent: Option<Id>,
+    workers: bool,
+    cancellable: bool,
+}
+
+impl<I> ProgressParIter<I> {
+    /// Create the report under `parent`, rather than using [`new`]'s parenting.
+    pub fn with_parent(mut self, parent: Id) -> Self {
+        self.parent = Some(parent);
+        self
+    }
+
+    /// Show a child report for each worker thread processing items.
+    ///
+    /// The worker reports are closed when the iterator completes.
+    pub fn show_workers(mut self, show_workers: bool) -> Self {
+        self.workers = show_workers;
+        self
+    }
+
+    /// Stop processing items early if the report is [cancelled](Tx::cancelled).
+    ///
+    /// Each job checks the cancellation when it sends its batched increment, and the parallel
+    /// iterator stops on a best effort basis, see [`ParallelIterator::while_some`].
+    pub fn cancellable(mut self, cancellable: bool) -> Self {
+        self.cancellable = cancellable;
+        self
+    }
+}
+
+impl<I: ParallelIterator> ParallelIterator for ProgressParIter<I> {
+    type Item = I::Item;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        let Self {
+            iter,
+            hd,
+            label,
+            parent,
+            workers,
+            cancellable,
+        } = self;
+
+        let rpt = match parent {
+            Some(parent) => hd.new_with_parent(parent),
+            None => hd.new(),
+        }
+        .label(label)
+        .set_len(iter.opt_len().map(|x| x as u64));
+
+        let workers = workers.then(|| Workers {
+            parent: rpt,
+            txs: Mutex::new(Vec::new()),
+        });
+
+        let x = iter
+            .map_init(
+                || Local::new(&rpt, workers.as_ref(), cancellable),
+                |local, item| {
+                    if local.cancelled {
+                        None
+                    } else {
+                        local.inc();
+                        Some(item)
+                    }
+                },
+            )
+            .while_some()
+            .drive_unindexed(consumer);
+
+        if let Some(workers) = workers {
+            for tx in workers.txs.into_inner().into_iter().flatten() {
+                tx.close();
+            }
+        }
+
+        if !rpt.cancelled() {
+            rpt.finish();
+        }
+
+        x
+    }
+}
+
+/// Child reports for each worker thread, indexed by the rayon thread index.
+struct Workers {
+    parent: Tx,
+    txs: Mutex<Vec<Option<Tx>>>,
+}
+
+impl Workers {
+    fn get(&self) -> Option<Tx> {
+        let idx = rayon::current_thread_index()?;
+        let mut txs = self.txs.lock();
+        if txs.len() <= idx {
+            txs.resize(idx + 1, None);
+        }
+
+        let tx = txs[idx].get_or_insert_with(|| {
+            self.parent
+                .instance()
+                .map(|hd| hd.new_with_parent(self.parent.id()))
+                .unwrap_or_else(Tx::void)
+                .label(format!("worker {idx}"))
+        });
+
+        Some(*tx)
+    }
+}
+
+/// A job's local increment accumulator.
+struct Local {
+    rpt: Tx,
+    worker: Option<Tx>,
+    pending: u64,
+    cancellable: bool,
+    /// The report was cancelled when last checked.
+    cancelled: bool,
+}
+
+impl Local {
+    fn new(rpt: &Tx, workers: Option<&Workers>, cancellable: bool) -> Self {
+        Self {
+            rpt: *rpt,
+            worker: workers.and_then(Workers::get),
+            pending: 0,
+            cancellable,
+            cancelled: cancellable && rpt.cancelled(),
+        }
+    }
+
+    fn inc(&mut self) {
+        self.pending += 1;
+        if self.pending >= BATCH {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.pending > 0 {
+            self.rpt.inc_by(self.pending);
+            if let Some(x) = &self.worker {
+                x.inc_by(self.pending);
+            }
+            self.pending = 0;
+
+            // checked with each batch, rather than for each item
+            self.cancelled = self.cancellable && self.rpt.cancelled();
+        }
+    }
+}
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
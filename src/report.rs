@@ -1,6 +1,7 @@
 //! The public structures of progress reports.
 //!
 //! The data structures are serialisable with the `serde` feature.
+use crate::Id;
 use std::fmt;
 
 // ###### PROGRESS #############################################################
@@ -50,8 +51,10 @@ pub struct Report {
 /// The state of the progress.
 ///
 /// This structure is serialisable with the `serde` feature.
+/// New states may be added, so matching on a state requires a wildcard arm.
 #[derive(Debug, Clone, Copy, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum State {
     /// The report is in progress.
     InProgress {
@@ -80,6 +83,13 @@ pub enum State {
     },
     /// The progress was cancelled.
     Cancelled,
+    /// The progress reporter was dropped before it was finished.
+    ///
+    /// This occurs when a [`ReportGuard`] is dropped with [`OnDrop::Abandon`].
+    ///
+    /// [`ReportGuard`]: crate::ReportGuard
+    /// [`OnDrop::Abandon`]: crate::OnDrop::Abandon
+    Abandoned,
 }
 
 impl Default for State {
@@ -93,6 +103,88 @@ impl Default for State {
     }
 }
 
+// ###### EVENT ################################################################
+
+/// A fine-grained change to the progress tree.
+///
+/// Events are emitted as the consumer loop processes updates, and are delivered to
+/// [`Consume::event`](crate::Consume::event).
+/// Note that position updates are coalesced, so [`Event::Progressed`] is emitted when the position
+/// is sampled on a debounce tick.
+///
+/// This structure is serialisable with the `serde` feature.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Event {
+    /// A report was created.
+    Created {
+        /// The report identifier.
+        id: Id,
+        /// The report's parent, if it is not a root.
+        parent: Option<Id>,
+    },
+    /// A report's label was changed.
+    LabelChanged {
+        /// The report identifier.
+        id: Id,
+        /// The new label.
+        label: String,
+    },
+    /// A report's description was changed.
+    DescChanged {
+        /// The report identifier.
+        id: Id,
+        /// The new description.
+        desc: String,
+    },
+    /// A report's position was changed.
+    Progressed {
+        /// The report identifier.
+        id: Id,
+        /// The new position.
+        pos: u64,
+    },
+    /// An accumulation message was added to a report.
+    MessageAdded {
+        /// The report identifier.
+        id: Id,
+        /// The added message.
+        message: Message,
+    },
+    /// A report was finished.
+    Finished(Id),
+    /// A report was dropped before it was finished.
+    Abandoned(Id),
+    /// A report was closed and removed from the tree.
+    Closed(Id),
+    /// A report was cancelled.
+    ///
+    /// If `None`, the consumer loop was flagged for cancellation.
+    Cancelled(Option<Id>),
+    /// The consumer loop was reset, removing all reports.
+    Reset,
+}
+
+impl Event {
+    /// The identifier of the report the event applies to.
+    ///
+    /// Returns `None` for events which apply to all reports.
+    pub fn id(&self) -> Option<Id> {
+        match self {
+            Event::Created { id, .. }
+            | Event::LabelChanged { id, .. }
+            | Event::DescChanged { id, .. }
+            | Event::Progressed { id, .. }
+            | Event::MessageAdded { id, .. }
+            | Event::Finished(id)
+            | Event::Abandoned(id)
+            | Event::Closed(id)
+            | Event::Cancelled(Some(id)) => Some(*id),
+            Event::Cancelled(None) | Event::Reset => None,
+        }
+    }
+}
+
 // ###### MESSAGE ##############################################################
 
 /// An accumulation message.
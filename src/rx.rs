@@ -1,220 +1,899 @@
 use super::*;
 use crate::{
     flat_tree::FlatTree,
-    report::{Message, Report, State},
+    report::{Event, Message, Report, State},
+    subscription::Subscriber,
+    tx::Token,
+};
+use flume::{Receiver, RecvTimeoutError, Sender, TryRecvError};
+use parking_lot::Mutex;
+use std::{
+    any::Any,
+    collections::BTreeSet,
+    panic::{catch_unwind, AssertUnwindSafe},
 };
-use flume::Receiver;
-use std::collections::BTreeSet;
 use Payload::*;
 
-pub(crate) fn spawn<C: Consume>(rx: Receiver<Payload>, mut consumer: C) {
-    let debounce = consumer.debounce();
+/// A consumer to replace the loop's consumer with.
+pub(crate) struct Replacement(pub(crate) Box<dyn Consume + Send>);
+
+impl std::fmt::Debug for Replacement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Replacement")
+    }
+}
+
+/// The receiver side of a consumer loop, driven from the caller's thread.
+///
+/// A `Pump` is created with [`Builder::pump`], instead of spawning a thread for the consumer
+/// loop. This suits single-threaded environments, deterministic tests, and event loops (such as
+/// GUI frameworks) which drive rendering themselves.
+/// Pumping processes the pending updates, applies the debounce, and invokes the consumer on the
+/// caller's thread. The consumer does not need to be `Send`, so neither is the pump.
+///
+/// The consumer is finalised when the progress reporter is [`disable`]d and the pump has
+/// processed the remaining updates, or when the pump is dropped.
+/// Note that [`fetch`] and [`shutdown`] wait for the pump, so they should not be called from the
+/// pumping thread.
+///
+/// # Example
+/// ```rust
+/// use howudoin::Builder;
+/// use std::time::Duration;
+///
+/// let hd = howudoin::Howudoin::default();
+/// let mut pump = Builder::new().pump_on(&hd, howudoin::consumers::Noop::default());
+///
+/// let rpt = hd.new().label("Progress");
+/// rpt.finish();
+///
+/// // in the application's loop
+/// pump.try_pump();
+/// ```
+pub struct Pump(Looper<dyn Consume>);
+
+impl Pump {
+    pub(crate) fn new(looper: Looper<dyn Consume>) -> Self {
+        Self(looper)
+    }
+
+    /// Process the updates for `timeout`, invoking the consumer on each debounce.
+    ///
+    /// This blocks for the whole `timeout`, unless the pump finishes.
+    /// Returns `false` if the pump has finished, see [`Pump::is_finished`].
+    pub fn pump(&mut self, timeout: Duration) -> bool {
+        self.0.pump(timeout)
+    }
+
+    /// Process the pending updates without waiting, invoking the consumer if the debounce has
+    /// elapsed.
+    ///
+    /// Returns `false` if the pump has finished, see [`Pump::is_finished`].
+    pub fn try_pump(&mut self) -> bool {
+        self.0.try_pump()
+    }
+
+    /// The progress reporter was disabled, and the consumer has been finalised.
+    pub fn is_finished(&self) -> bool {
+        self.0.is_finished()
+    }
+}
+
+/// The boxed consumer a consumer loop holds.
+///
+/// This is `dyn Consume + Send` for a loop running on another thread or task, and `dyn Consume`
+/// for a [`Pump`], which invokes the consumer on the caller's thread.
+pub(crate) trait Slot: Consume {
+    /// Hold a consumer sent to the loop, which is always `Send`.
+    fn from_send(x: Box<dyn Consume + Send>) -> Box<Self>;
+}
+
+impl Slot for dyn Consume {
+    fn from_send(x: Box<dyn Consume + Send>) -> Box<Self> {
+        x
+    }
+}
+
+impl Slot for dyn Consume + Send {
+    fn from_send(x: Box<dyn Consume + Send>) -> Box<Self> {
+        x
+    }
+}
+
+/// The receiver side of a consumer loop, holding the consumer as a `B`.
+pub(crate) struct Looper<B: ?Sized + Slot> {
+    rx: Receiver<Payload>,
+    consumer: Guarded<B>,
+    controller: Controller,
+    last: Instant,
+    started: bool,
+    /// Disconnects when the loop finishes.
+    done: Option<Sender<()>>,
+}
+
+impl<B: ?Sized + Slot> Looper<B> {
+    pub(crate) fn new(rx: Receiver<Payload>, consumer: Guarded<B>, done: Sender<()>) -> Self {
+        Self {
+            rx,
+            consumer,
+            controller: Controller::default(),
+            last: Instant::now(),
+            started: false,
+            done: Some(done),
+        }
+    }
+
+    /// Run the consumer loop until all the senders are dropped.
+    pub(crate) fn run(mut self) {
+        while self.step(None) {}
+    }
+
+    /// Run the consumer loop as a task until all the senders are dropped.
+    ///
+    /// The debounce is driven by a [`tokio::time::Interval`], rather than a receive deadline.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn run_async(mut self) {
+        use tokio::time::{interval_at, Interval, MissedTickBehavior};
+
+        let mut ticks: Option<(Duration, Interval)> = None;
+
+        while !self.is_finished() {
+            self.start();
+
+            // the debounce changes if the consumer is replaced
+            let debounce = self.consumer.period();
+            if debounce.is_zero() {
+                ticks = None;
+            } else if ticks.as_ref().is_none_or(|(x, _)| *x != debounce) {
+                let mut x = interval_at((self.last + debounce).into(), debounce);
+                x.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                ticks = Some((debounce, x));
+            }
+
+            tokio::select! {
+                x = self.rx.recv_async() => match x {
+                    Ok(x) => {
+                        self.handle(x);
+                        if ticks.is_none() {
+                            self.flush_tick();
+                        }
+                    }
+                    // all senders dropped and the channel is drained, exit receiver loop
+                    Err(_) => self.finish(),
+                },
+                Some(_) = async {
+                    match ticks.as_mut() {
+                        Some((_, x)) => Some(x.tick().await),
+                        None => None,
+                    }
+                }, if ticks.is_some() => self.flush_tick(),
+            }
+        }
+    }
+
+    /// See [`Pump::pump`].
+    fn pump(&mut self, timeout: Duration) -> bool {
+        let until = Instant::now().checked_add(timeout);
+        while until.is_none_or(|x| Instant::now() < x) {
+            if !self.step(until) {
+                return false;
+            }
+        }
 
-    let mut controller = Controller::default();
-    let mut chgd_buf = BTreeSet::new();
-    let mut last = Instant::now();
+        !self.is_finished()
+    }
 
-    loop {
-        if rx.is_disconnected() {
-            break; // static tx dropped, exit receiver loop
+    /// See [`Pump::try_pump`].
+    fn try_pump(&mut self) -> bool {
+        if self.is_finished() {
+            return false;
         }
 
-        // use a timeout to avoid thrashing the loop
-        let x = if debounce.is_zero() {
-            rx.recv().ok()
-        } else {
-            rx.recv_timeout(debounce).ok()
+        self.start();
+
+        loop {
+            match self.rx.try_recv() {
+                Ok(x) => self.handle(x),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.finish();
+                    return false;
+                }
+            }
+        }
+
+        let debounce = self.consumer.period();
+        self.tick(debounce);
+        true
+    }
+
+    /// The progress reporter was disabled, and the consumer has been finalised.
+    fn is_finished(&self) -> bool {
+        self.done.is_none()
+    }
+
+    /// Wait for an update until the next debounce tick, or `until`.
+    ///
+    /// Returns `false` if the pump has finished.
+    fn step(&mut self, until: Option<Instant>) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+
+        self.start();
+
+        // the debounce changes if the consumer is replaced
+        let debounce = self.consumer.period();
+
+        // wait until the next debounce tick to avoid thrashing the loop
+        let deadline = match (!debounce.is_zero()).then(|| self.last + debounce) {
+            Some(x) => Some(until.map_or(x, |y| x.min(y))),
+            None => until,
         };
 
-        if let Some(x) = x.and_then(|x| controller.process(x)) {
-            chgd_buf.insert(x);
+        let x = match deadline {
+            Some(x) => self.rx.recv_deadline(x),
+            None => self.rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match x {
+            Ok(x) => self.handle(x),
+            Err(RecvTimeoutError::Timeout) => (),
+            // all senders dropped and the channel is drained, exit receiver loop
+            Err(RecvTimeoutError::Disconnected) => {
+                self.finish();
+                return false;
+            }
         }
 
-        if last.elapsed() >= debounce {
+        self.tick(debounce);
+        true
+    }
+
+    fn start(&mut self) {
+        if !self.started {
+            self.started = true;
+            self.consumer.on_start();
+        }
+    }
+
+    fn handle(&mut self, payload: Payload) {
+        let Self {
+            consumer,
+            controller,
+            last,
+            ..
+        } = self;
+
+        match payload {
+            Reset => {
+                controller.process(Reset);
+                controller.emit(consumer);
+                // deliver the closed reports before notifying the consumer
+                controller.flush(consumer);
+                consumer.on_reset(controller);
+            }
+            Replace(Replacement(x)) => {
+                // finalise the old consumer with its pending changes
+                controller.flush(consumer);
+                consumer.on_shutdown(controller);
+
+                consumer.set(B::from_send(x));
+                consumer.on_start();
+                controller.replay(consumer);
+                *last = Instant::now();
+            }
+            x => {
+                controller.process(x);
+                controller.emit(consumer);
+            }
+        }
+    }
+
+    fn tick(&mut self, debounce: Duration) {
+        if self.last.elapsed() >= debounce {
             // debounce duration has occurred; can update the consumer with any changes
+            self.flush_tick();
+        }
+    }
 
-            while let Some(id) = chgd_buf.pop_first() {
-                if let Some(Progress_ {
-                    rpt,
-                    children: _,
-                    parent,
-                    started: _,
-                }) = controller.ps.get(&id)
-                {
-                    consumer.rpt(rpt, id, *parent, &controller);
-                } else {
-                    consumer.closed(id);
-                }
+    fn flush_tick(&mut self) {
+        self.controller.flush(&mut self.consumer);
+        self.consumer.on_tick(&self.controller);
+        self.last = Instant::now();
+    }
+
+    fn finish(&mut self) {
+        if self.is_finished() {
+            return;
+        }
+
+        self.start();
+        // process the remaining updates, if the pump is dropped early
+        while let Ok(x) = self.rx.try_recv() {
+            self.handle(x);
+        }
+        // deliver the changes since the last debounce before finalising the consumer
+        self.controller.flush(&mut self.consumer);
+        self.consumer.on_shutdown(&self.controller);
+        // end the subscriptions before signalling the pump has finished
+        self.controller.subs.clear();
+        self.done = None;
+    }
+}
+
+impl<B: ?Sized + Slot> Drop for Looper<B> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+// ###### PANIC ISOLATION ######################################################
+
+/// Statistics of a consumer loop.
+///
+/// See [`stats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of panics caught from the consumer's callbacks.
+    pub panics: u64,
+    /// The message of the last caught panic.
+    pub last_panic: Option<String>,
+    /// The consumer panicked and was replaced with the [fallback](Builder::fallback).
+    pub fallback: bool,
+    /// There is no consumer, since it panicked without a fallback.
+    ///
+    /// The consumer loop keeps tracking progress, so [`fetch`] and cancellation still work.
+    pub failed: bool,
+}
+
+/// The loop's consumer, isolating panics from the consumer's callbacks.
+///
+/// A consumer which panics is dropped, and replaced with the fallback consumer if there is one.
+pub(crate) struct Guarded<B: ?Sized> {
+    consumer: Option<Box<B>>,
+    fallback: Option<Box<B>>,
+    stats: Arc<Mutex<Stats>>,
+    /// The fallback consumer needs the tree replayed.
+    replay: bool,
+}
+
+impl<B: ?Sized + Slot> Guarded<B> {
+    pub(crate) fn new(
+        consumer: Box<B>,
+        fallback: Option<Box<B>>,
+        stats: Arc<Mutex<Stats>>,
+    ) -> Self {
+        Self {
+            consumer: Some(consumer),
+            fallback,
+            stats,
+            replay: false,
+        }
+    }
+
+    /// Replace the consumer.
+    fn set(&mut self, consumer: Box<B>) {
+        self.drop_consumer();
+        self.consumer = Some(consumer);
+        self.replay = false;
+        self.stats.lock().failed = false;
+    }
+
+    /// The consumer's debounce, or the default if there is no consumer.
+    fn period(&mut self) -> Duration {
+        let x = self.call(|c| c.debounce());
+        x.unwrap_or(Duration::from_millis(50))
+    }
+
+    /// Invoke `f` with the consumer, catching a panic.
+    fn call<T, F: FnOnce(&mut B) -> T>(&mut self, f: F) -> Option<T> {
+        let c = self.consumer.as_deref_mut()?;
+        match catch_unwind(AssertUnwindSafe(|| f(c))) {
+            Ok(x) => Some(x),
+            Err(e) => {
+                self.panicked(e);
+                None
             }
+        }
+    }
+
+    /// Invoke `f` with the consumer, first replaying the tree to a fallback consumer.
+    fn call_with<F: FnOnce(&mut B)>(&mut self, controller: &Controller, f: F) {
+        if std::mem::take(&mut self.replay) {
+            controller.replay(self);
+        }
+        self.call(f);
+    }
+
+    fn panicked(&mut self, e: Box<dyn Any + Send>) {
+        let msg = e
+            .downcast_ref::<&str>()
+            .map(|x| x.to_string())
+            .or_else(|| e.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "consumer panicked".to_string());
+
+        self.drop_consumer();
+
+        let fallback = self.fallback.take();
+        let failed = fallback.is_none();
+        {
+            let mut stats = self.stats.lock();
+            stats.panics += 1;
+            stats.last_panic = Some(msg);
+            stats.fallback |= !failed;
+            stats.failed = failed;
+        }
 
-            last = Instant::now();
+        if let Some(x) = fallback {
+            self.consumer = Some(x);
+            self.replay = true;
+            self.call(|c| c.on_start());
         }
     }
+
+    fn drop_consumer(&mut self) {
+        // a panicking consumer might also panic when dropped
+        let x = self.consumer.take();
+        catch_unwind(AssertUnwindSafe(|| drop(x))).ok();
+    }
+}
+
+impl<B: ?Sized + Slot> Consume for Guarded<B> {
+    fn debounce(&self) -> Duration {
+        self.consumer
+            .as_ref()
+            .map(|c| c.debounce())
+            .unwrap_or(Duration::from_millis(50))
+    }
+
+    fn rpt(&mut self, report: &Report, id: Id, parent: Option<Id>, controller: &Controller) {
+        self.call_with(controller, |c| c.rpt(report, id, parent, controller));
+    }
+
+    fn closed(&mut self, id: Id) {
+        self.call(|c| c.closed(id));
+    }
+
+    fn batch(&mut self, changes: &[Change], controller: &Controller) {
+        self.call_with(controller, |c| c.batch(changes, controller));
+    }
+
+    fn event(&mut self, event: &Event, controller: &Controller) {
+        self.call_with(controller, |c| c.event(event, controller));
+    }
+
+    fn on_start(&mut self) {
+        self.call(|c| c.on_start());
+    }
+
+    fn on_tick(&mut self, controller: &Controller) {
+        self.call_with(controller, |c| c.on_tick(controller));
+    }
+
+    fn on_reset(&mut self, controller: &Controller) {
+        self.call_with(controller, |c| c.on_reset(controller));
+    }
+
+    fn on_shutdown(&mut self, controller: &Controller) {
+        self.call_with(controller, |c| c.on_shutdown(controller));
+    }
 }
 
 /// The progress consumer loop controller.
+///
+/// The controller tracks the progress tree, and is passed to the consumer's callbacks for
+/// querying the tree (such as a report's [depth](Controller::depth) or
+/// [children](Controller::children)) without cloning it.
 #[derive(Default)]
 pub struct Controller {
     ps: FlatTree<Id, Progress_>,
     last: Option<Id>,
     cancelled: bool,
-    nextid: Id,
+    chgd: BTreeSet<Id>,
+    events: Vec<Event>,
+    subs: Vec<Subscriber>,
 }
 
 impl Controller {
-    fn next_id(&mut self) -> Id {
-        let id = self.nextid;
-        self.nextid = self.nextid.wrapping_add(1);
-        id
+    /// Deliver the events emitted since the last call.
+    fn emit<C: Consume>(&mut self, consumer: &mut C) {
+        let mut events = std::mem::take(&mut self.events);
+        for x in events.drain(..) {
+            // remove the subscriptions which have been dropped
+            self.subs.retain(|s| s.send(&x));
+            consumer.event(&x, self);
+        }
+        // reuse the allocation
+        self.events = events;
     }
 
-    fn process(&mut self, payload: Payload) -> Option<Id> {
+    /// Update the consumer with any changes.
+    fn flush<C: Consume>(&mut self, consumer: &mut C) {
+        self.sample();
+        self.emit(consumer);
+
+        if self.chgd.is_empty() {
+            return;
+        }
+
+        let chgd = std::mem::take(&mut self.chgd);
+        let changes = chgd
+            .into_iter()
+            .map(|id| self.change(id))
+            .collect::<Vec<_>>();
+
+        consumer.batch(&changes, self);
+    }
+
+    /// Deliver the whole tree to the consumer, parents before their children.
+    fn replay<C: Consume>(&self, consumer: &mut C) {
+        let changes = self
+            .iter()
+            .map(|(id, _)| self.change(id))
+            .collect::<Vec<_>>();
+
+        if !changes.is_empty() {
+            consumer.batch(&changes, self);
+        }
+    }
+
+    /// The change to deliver for the report with `id`.
+    pub(crate) fn change(&self, id: Id) -> Change<'_> {
+        match self.ps.get(&id) {
+            Some(Progress_ {
+                rpt,
+                children: _,
+                parent,
+                started: _,
+                token: _,
+                sampled: _,
+            }) => Change::Updated {
+                report: rpt,
+                id,
+                parent: *parent,
+            },
+            None => Change::Closed(id),
+        }
+    }
+
+    fn process(&mut self, payload: Payload) {
         match payload {
-            AddReport(None, tx) => {
-                let id = match self.last {
-                    Some(parent) => self.add_child(parent),
-                    None => self.add_root(),
+            AddReport(id, None, token) => {
+                match self.last {
+                    Some(parent) => self.add_child(id, parent, token),
+                    None => self.add_root(id, token),
                 };
 
-                tx.send(id).ok();
-                Some(id)
+                self.created(id);
             }
 
-            AddReport(Some(parent), tx) => {
-                let id = self.add_child(parent);
-                tx.send(id).ok();
-                Some(id)
+            AddReport(id, Some(parent), token) => {
+                self.add_child(id, parent, token);
+                self.created(id);
             }
 
-            AddRootReport(tx) => {
-                let id = self.add_root();
-                tx.send(id).ok();
-                Some(id)
+            AddRootReport(id, token) => {
+                self.add_root(id, token);
+                self.created(id);
             }
 
             Fetch(tx) => {
+                self.sample();
                 tx.send(self.build_progress_tree()).ok();
-                None
             }
 
             SetLabel(id, label) => {
-                self.set(id, |x, _| x.label = label);
-                Some(id)
+                if self.set(id, |x, _| x.label.clone_from(&label)) {
+                    self.events.push(Event::LabelChanged { id, label });
+                }
+                self.chgd.insert(id);
             }
 
-            SetDesc(id, d) => {
-                self.set(id, |x, _| x.desc = d);
-                Some(id)
+            SetDesc(id, desc) => {
+                if self.set(id, |x, _| x.desc.clone_from(&desc)) {
+                    self.events.push(Event::DescChanged { id, desc });
+                }
+                self.chgd.insert(id);
             }
 
             SetLen(id, len) => {
                 self.set(id, |x, _| x.set_len(len));
-                Some(id)
-            }
-
-            Inc(id, by) => {
-                self.set(id, |x, e| x.inc_pos(by, e));
-                Some(id)
+                self.chgd.insert(id);
             }
 
-            SetPos(id, pos) => {
-                self.set(id, |x, e| x.update_pos(pos, e));
-                Some(id)
-            }
+            // the positions are sampled when flushing
+            PosChanged => (),
 
             SetFmtBytes(id, y) => {
                 self.set(id, |x, _| x.set_fmt_as_bytes(y));
-                Some(id)
+                self.chgd.insert(id);
             }
 
             Accum(id, severity, msg) => {
-                self.set(id, |x, _| x.accums.push(Message { severity, msg }));
-                Some(id)
+                let message = Message { severity, msg };
+                if self.set(id, |x, _| x.accums.push(message.clone())) {
+                    self.events.push(Event::MessageAdded { id, message });
+                }
+                self.chgd.insert(id);
             }
 
             Finish(id) => {
-                self.set(id, |x, e| {
+                self.settle(id);
+                let finished = self.set(id, |x, e| {
                     x.state = State::Completed {
                         duration: e.as_secs_f32(),
                     }
                 });
 
+                if finished {
+                    self.events.push(Event::Finished(id));
+                }
+
                 // if finished, do not keep around as a parent
                 if self.last == Some(id) {
                     self.last = None;
                 }
 
-                Some(id)
+                self.chgd.insert(id);
+            }
+
+            Abandon(id) => {
+                self.settle(id);
+                let mut abandoned = false;
+                self.set(id, |x, _| {
+                    if matches!(x.state, State::InProgress { .. }) {
+                        x.state = State::Abandoned;
+                        abandoned = true;
+                    }
+                });
+
+                if abandoned {
+                    self.events.push(Event::Abandoned(id));
+                }
+
+                if self.last == Some(id) {
+                    self.last = None;
+                }
+
+                self.chgd.insert(id);
             }
 
             Close(id) => {
-                self.ps.remove(&id);
+                self.settle(id);
+                if let Some(x) = self.ps.remove(&id) {
+                    self.events.push(Event::Closed(id));
+
+                    // the children are still tracked, so they become roots
+                    for child in x.children {
+                        if let Some(c) = self.ps.get_mut(&child) {
+                            c.parent = None;
+                            self.ps.roots.insert(child);
+                            self.chgd.insert(child);
+                        }
+                    }
+                }
 
                 if self.last == Some(id) {
                     self.last = None;
                 }
 
-                Some(id)
+                self.chgd.insert(id);
             }
 
-            Cancel => {
+            Cancel(None) => {
                 self.cancelled = true;
-                None
+                self.events.push(Event::Cancelled(None));
             }
 
-            Cancelled(tx) => {
-                tx.send(self.cancelled).ok();
-                None
+            Cancel(Some(id)) => {
+                self.cancel_subtree(id);
             }
 
+            Subscribe(x) => {
+                self.subs.push(x);
+            }
+
+            Replace(_) => (), // handled by the consumer loop
+
             Reset => {
-                *self = Self::default();
-                None
+                // keep the changes, notifying the consumer that the reports are closed
+                for (id, x) in &self.ps.nodes {
+                    x.token.set_cancelled(false);
+                    self.chgd.insert(*id);
+                }
+
+                self.ps = FlatTree::default();
+                self.last = None;
+                self.cancelled = false;
+                self.events.push(Event::Reset);
             }
         }
     }
 
-    fn add_root(&mut self) -> Id {
-        let id = self.next_id();
-        self.ps.insert_root(
-            id,
-            Progress_ {
-                parent: None,
-                ..Progress_::root()
-            },
-        );
+    fn created(&mut self, id: Id) {
+        if let Some(x) = self.ps.get(&id) {
+            self.events.push(Event::Created {
+                id,
+                parent: x.parent,
+            });
+        }
+        self.chgd.insert(id);
+    }
+
+    fn add_root(&mut self, id: Id, token: Token) {
+        self.ps.insert_root(id, Progress_::root(token));
         self.last = Some(id);
-        id
     }
 
-    fn add_child(&mut self, parent: Id) -> Id {
-        let id = self.next_id();
+    fn add_child(&mut self, id: Id, parent: Id, token: Token) {
+        let cancelled = self.is_cancelled(parent);
         match self.ps.get_mut(&parent) {
             Some(p) => {
                 p.children.push(id);
-                self.ps.insert(
-                    id,
-                    Progress_ {
-                        parent: Some(parent),
-                        ..Progress_::root()
-                    },
-                );
+                let mut x = Progress_ {
+                    parent: Some(parent),
+                    ..Progress_::root(token)
+                };
+
+                // reports under a cancelled parent are also cancelled
+                if cancelled {
+                    x.rpt.state = State::Cancelled;
+                    x.token.set_cancelled(true);
+                }
+
+                self.ps.insert(id, x);
             }
             None => {
-                self.ps.insert_root(id, Progress_::root());
+                self.ps.insert_root(id, Progress_::root(token));
             }
         }
 
         self.last = Some(id);
-        id
     }
 
-    fn set<F: FnOnce(&mut Report, Duration)>(&mut self, id: Id, f: F) {
-        if let Some(x) = self.ps.get_mut(&id) {
-            f(&mut x.rpt, x.started.elapsed())
+    /// Sample the positions of the reports in progress.
+    ///
+    /// Every report is compared with its token, rather than only those the loop was notified of,
+    /// since a reporter which reads a stale dirty flag does not notify the loop.
+    fn sample(&mut self) {
+        for (&id, x) in &mut self.ps.nodes {
+            if let Some(pos) = x.sample() {
+                self.events.push(Event::Progressed { id, pos });
+                self.chgd.insert(id);
+            }
+        }
+    }
+
+    /// Sample the pending position of the report with `id` before it leaves the in progress
+    /// state, so the progress is delivered before the terminal event.
+    fn settle(&mut self, id: Id) {
+        if let Some(pos) = self.ps.get_mut(&id).and_then(Progress_::sample) {
+            self.events.push(Event::Progressed { id, pos });
+            self.chgd.insert(id);
+        }
+    }
+
+    fn cancel_subtree(&mut self, id: Id) {
+        self.settle(id);
+        let children = match self.ps.get_mut(&id) {
+            Some(x) => {
+                if matches!(x.rpt.state, State::InProgress { .. }) {
+                    x.rpt.state = State::Cancelled;
+                    self.events.push(Event::Cancelled(Some(id)));
+                }
+                x.token.set_cancelled(true);
+                x.children.clone()
+            }
+            None => return,
+        };
+
+        self.chgd.insert(id);
+
+        for child in children {
+            self.cancel_subtree(child);
         }
     }
 
+    /// Update the report with `id`, returning if it exists.
+    fn set<F: FnOnce(&mut Report, Duration)>(&mut self, id: Id, f: F) -> bool {
+        match self.ps.get_mut(&id) {
+            Some(x) => {
+                f(&mut x.rpt, x.started.elapsed());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The report with `id`, if it is tracked.
+    pub fn get(&self, id: Id) -> Option<&Report> {
+        self.ps.get(&id).map(|x| &x.rpt)
+    }
+
+    /// The parent of the report with `id`.
+    ///
+    /// Returns `None` for root reports and untracked reports.
+    /// Note that the children of a closed report become root reports.
+    pub fn parent(&self, id: Id) -> Option<Id> {
+        self.ps
+            .get(&id)?
+            .parent
+            .filter(|p| self.ps.contains_node(p))
+    }
+
+    /// The children of the report with `id`, in creation order.
+    ///
+    /// Closed children are skipped.
+    pub fn children(&self, id: Id) -> impl Iterator<Item = Id> + '_ {
+        self.ps
+            .get(&id)
+            .into_iter()
+            .flat_map(|x| &x.children)
+            .copied()
+            .filter(|x| self.ps.contains_node(x))
+    }
+
+    /// The ancestors of the report with `id`, starting with its parent.
+    pub fn ancestors(&self, id: Id) -> impl Iterator<Item = Id> + '_ {
+        std::iter::successors(self.parent(id), |x| self.parent(*x))
+    }
+
+    /// The number of ancestors of the report with `id`.
+    ///
+    /// Root reports have a depth of zero. Returns `None` if the report is not tracked.
+    pub fn depth(&self, id: Id) -> Option<usize> {
+        self.ps
+            .contains_node(&id)
+            .then(|| self.ancestors(id).count())
+    }
+
+    /// The root reports, in creation order.
+    pub fn roots(&self) -> impl Iterator<Item = Id> + '_ {
+        self.ps.roots().map(|(id, _)| *id)
+    }
+
+    /// Iterate over the tracked reports in tree order, parents before their children.
+    ///
+    /// This matches the order of [`Controller::build_progress_tree`], without cloning the
+    /// reports.
+    pub fn iter(&self) -> impl Iterator<Item = (Id, &Report)> + '_ {
+        let mut stack = self.roots().collect::<Vec<_>>();
+        stack.reverse();
+
+        std::iter::from_fn(move || loop {
+            let id = stack.pop()?;
+            if let Some(x) = self.ps.get(&id) {
+                stack.extend(x.children.iter().rev());
+                return Some((id, &x.rpt));
+            }
+        })
+    }
+
+    /// The time since the report with `id` was created.
+    pub fn elapsed(&self, id: Id) -> Option<Duration> {
+        self.ps.get(&id).map(|x| x.started.elapsed())
+    }
+
+    /// The report with `id`, or any of its ancestors, is in the [cancelled](State::Cancelled)
+    /// state.
+    ///
+    /// Note that this does not reflect the global [`cancel`] flag.
+    pub fn is_cancelled(&self, mut id: Id) -> bool {
+        while let Some(x) = self.ps.get(&id) {
+            if x.rpt.state == State::Cancelled {
+                return true;
+            }
+
+            match x.parent {
+                Some(p) => id = p,
+                None => break,
+            }
+        }
+
+        false
+    }
+
     /// Build the progress tree.
     ///
     /// This is utilised by [`fetch`].
@@ -232,6 +911,8 @@ impl Controller {
                  children,
                  parent: _,
                  started: _,
+                 token: _,
+                 sampled: _,
              }| {
                 let children = children
                     .iter()
@@ -252,17 +933,39 @@ struct Progress_ {
     children: Vec<Id>,
     parent: Option<Id>,
     started: Instant,
+    token: Token,
+    /// The position last sampled from the token.
+    sampled: u64,
 }
 
 impl Progress_ {
-    fn root() -> Self {
+    fn root(token: Token) -> Self {
         Self {
             rpt: Default::default(),
             children: Default::default(),
             parent: None,
             started: Instant::now(),
+            token,
+            sampled: 0,
         }
     }
+
+    /// Sample the token's position, returning it if it has moved.
+    fn sample(&mut self) -> Option<u64> {
+        // the position is only tracked while in progress
+        if !matches!(self.rpt.state, State::InProgress { .. }) {
+            return None;
+        }
+
+        let pos = self.token.take_pos();
+        if pos == self.sampled {
+            return None;
+        }
+
+        self.sampled = pos;
+        self.rpt.update_pos(pos, self.started.elapsed());
+        Some(pos)
+    }
 }
 
 impl Report {
@@ -278,12 +981,6 @@ impl Report {
         }
     }
 
-    fn inc_pos(&mut self, ticks: u64, elapsed: Duration) {
-        if let State::InProgress { pos, .. } = &self.state {
-            self.update_pos(pos.saturating_add(ticks), elapsed)
-        }
-    }
-
     fn update_pos(&mut self, pos_: u64, elapsed: Duration) {
         if let State::InProgress {
             len,
@@ -0,0 +1,94 @@
+use super::*;
+use std::{cell::RefCell, marker::PhantomData};
+
+thread_local! {
+    /// The stack of entered reports on this thread, as `(instance, id)` pairs.
+    static SCOPES: RefCell<Vec<(usize, Id)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run `f` with `rpt` as the parent of any reports created with [`new`] on the current thread.
+///
+/// Scopes nest; reports created within the closure attach to the innermost scope.
+/// If no scope is active, [`new`] falls back to parenting under the _last_ report generated.
+///
+/// # Example
+/// ```rust
+/// let parent = howudoin::new_root().label("Parent");
+///
+/// howudoin::scope(&parent, || {
+///     // attached to parent
+///     let child = howudoin::new().label("Child");
+/// });
+/// ```
+pub fn scope<R, F: FnOnce() -> R>(rpt: &Tx, f: F) -> R {
+    let _scope = rpt.enter();
+    f()
+}
+
+/// A guard which keeps a report as the parent scope on the current thread.
+///
+/// Created with [`Tx::enter`], the scope is exited when the guard is dropped.
+/// The guard cannot be sent to another thread.
+#[must_use = "the scope is exited when the guard is dropped"]
+pub struct Scope {
+    entry: Option<(usize, Id)>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Tx {
+    /// Enter this report as the parent scope for reports created with [`new`] on the current
+    /// thread.
+    ///
+    /// The scope is exited when the returned guard is dropped.
+    ///
+    /// ```rust
+    /// let parent = howudoin::new_root().label("Parent");
+    /// let _scope = parent.enter();
+    ///
+    /// let child = howudoin::new().label("Child"); // attached to parent
+    /// ```
+    pub fn enter(&self) -> Scope {
+        let entry = self.instance().map(|hd| (hd.key(), self.id()));
+        if let Some(x) = entry {
+            SCOPES.with(|s| s.borrow_mut().push(x));
+        }
+
+        Scope {
+            entry,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        if let Some(x) = self.entry {
+            SCOPES.with(|s| {
+                let mut s = s.borrow_mut();
+                if let Some(i) = s.iter().rposition(|y| y == &x) {
+                    s.remove(i);
+                }
+            });
+        }
+    }
+}
+
+/// The innermost scoped parent which belongs to the instance.
+///
+/// Task-local scopes take precedence over the current thread's scopes.
+pub(crate) fn current(hd: &Howudoin) -> Option<Id> {
+    let key = hd.key();
+
+    #[cfg(feature = "tokio")]
+    if let Some(id) = crate::future::current(key) {
+        return Some(id);
+    }
+
+    SCOPES.with(|s| {
+        s.borrow()
+            .iter()
+            .rev()
+            .find(|(k, _)| *k == key)
+            .map(|(_, id)| *id)
+    })
+}
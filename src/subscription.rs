@@ -0,0 +1,104 @@
+//! Pulling progress events without a consumer.
+use super::*;
+use crate::report::Event;
+use flume::{Receiver, Sender, TrySendError};
+use std::sync::atomic::{AtomicU64, Ordering::*};
+
+/// A receiver of progress [`Event`]s, returned from [`subscribe`].
+///
+/// Events are buffered up to the subscription's capacity.
+/// If the subscriber falls behind, the events which do not fit are dropped and counted, see
+/// [`Subscription::lagged`].
+/// A lagging subscriber can resynchronise with a [`fetch`] of the progress tree.
+///
+/// The subscription ends when the consumer loop exits.
+pub struct Subscription {
+    rx: Receiver<Event>,
+    lagged: Arc<AtomicU64>,
+}
+
+/// The consumer loop's side of a [`Subscription`].
+#[derive(Debug)]
+pub(crate) struct Subscriber {
+    tx: Sender<Event>,
+    lagged: Arc<AtomicU64>,
+}
+
+/// Create a linked subscription and subscriber.
+pub(crate) fn channel(capacity: usize) -> (Subscription, Subscriber) {
+    let (tx, rx) = flume::bounded(capacity);
+    let lagged = Arc::new(AtomicU64::new(0));
+    (
+        Subscription {
+            rx,
+            lagged: lagged.clone(),
+        },
+        Subscriber { tx, lagged },
+    )
+}
+
+impl Subscription {
+    /// Wait for the next event.
+    ///
+    /// Returns `None` once the consumer loop has exited and the buffered events are received.
+    pub fn recv(&self) -> Option<Event> {
+        self.rx.recv().ok()
+    }
+
+    /// Wait for the next event, for at most `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Event> {
+        self.rx.recv_timeout(timeout).ok()
+    }
+
+    /// Receive the next event if one is buffered.
+    pub fn try_recv(&self) -> Option<Event> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Receive all the buffered events.
+    pub fn drain(&self) -> impl Iterator<Item = Event> + '_ {
+        self.rx.try_iter()
+    }
+
+    /// The consumer loop has exited.
+    ///
+    /// Note that events might still be buffered.
+    pub fn is_closed(&self) -> bool {
+        self.rx.is_disconnected()
+    }
+
+    /// The number of events dropped since the last call, because the buffer was full.
+    ///
+    /// If the subscriber has lagged, the received events are no longer a complete history.
+    pub fn lagged(&self) -> u64 {
+        self.lagged.swap(0, Relaxed)
+    }
+
+    /// An iterator which waits for each event, ending once the consumer loop has exited.
+    pub fn iter(&self) -> impl Iterator<Item = Event> + '_ {
+        self.rx.iter()
+    }
+}
+
+impl Subscriber {
+    /// Send the event, returning `false` if the subscription has been dropped.
+    pub(crate) fn send(&self, event: &Event) -> bool {
+        match self.tx.try_send(event.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.lagged.fetch_add(1, Relaxed);
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+impl std::fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Subscription")
+            .field("buffered", &self.rx.len())
+            .field("lagged", &self.lagged.load(Relaxed))
+            .finish()
+    }
+}
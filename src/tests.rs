@@ -1,8 +1,10 @@
 use super::*;
 use crate::report::*;
 
-fn init() {
-    super::init(consumers::Noop(Duration::ZERO));
+fn init() -> Howudoin {
+    let hd = Howudoin::default();
+    hd.init(consumers::Noop(Duration::ZERO));
+    hd
 }
 
 fn reset_rems(xs: Vec<Progress>) -> Vec<Progress> {
@@ -29,14 +31,14 @@ fn uninit_fetch() {
 
 #[test]
 fn report_creation_smoke_test() {
-    init();
+    let hd = init();
 
-    let a = new().label("a");
-    let _ = new().label("b");
-    let _ = new_with_parent(a.id()).label("c");
-    let _ = new_root().label("d");
+    let a = hd.new().label("a");
+    let _ = hd.new().label("b");
+    let _ = hd.new_with_parent(a.id()).label("c");
+    let _ = hd.new_root().label("d");
 
-    let f = fetch();
+    let f = hd.fetch();
     assert_eq!(
         f,
         Some(vec![
@@ -104,14 +106,14 @@ fn report_creation_smoke_test() {
 
 #[test]
 fn tx_api() {
-    init();
+    let hd = init();
 
-    let a = new().label("a").set_len(100).fmt_as_bytes(true);
+    let a = hd.new().label("a").set_len(100).fmt_as_bytes(true);
     a.desc("foo bar");
 
     a.inc().inc().inc_by(4_u8).add_err("errrr");
 
-    let f = reset_rems(fetch().unwrap());
+    let f = reset_rems(hd.fetch().unwrap());
     assert_eq!(
         f,
         vec![Progress {
@@ -135,7 +137,7 @@ fn tx_api() {
 
     a.set_pos(50_u8).add_warn("war");
 
-    let f = reset_rems(fetch().unwrap());
+    let f = reset_rems(hd.fetch().unwrap());
     assert_eq!(
         f,
         vec![Progress {
@@ -165,7 +167,7 @@ fn tx_api() {
 
     a.add_info("yo").finish();
 
-    let f = reset_rems(fetch().unwrap());
+    let f = reset_rems(hd.fetch().unwrap());
     assert_eq!(
         f,
         vec![Progress {
@@ -194,37 +196,37 @@ fn tx_api() {
 
     a.close();
 
-    let f = fetch();
+    let f = hd.fetch();
     assert_eq!(f, Some(vec![]));
 }
 
 #[test]
 fn cancel_test() {
-    disable();
+    let hd = Howudoin::default();
 
-    cancel();
-    assert_eq!(cancelled(), None);
+    hd.cancel();
+    assert_eq!(hd.cancelled(), None);
 
-    init();
+    hd.init(consumers::Noop(Duration::ZERO));
 
-    assert_eq!(cancelled(), Some(false));
+    assert_eq!(hd.cancelled(), Some(false));
 
-    let a = new();
+    let a = hd.new();
 
-    assert_eq!(a.cancelled(), false);
+    assert!(!a.cancelled());
 
-    cancel();
+    hd.cancel();
 
-    assert_eq!(a.cancelled(), true);
-    assert_eq!(cancelled(), Some(true));
+    assert!(a.cancelled());
+    assert_eq!(hd.cancelled(), Some(true));
 }
 
 #[test]
 fn reset_test() {
-    init();
+    let hd = init();
 
-    let _ = new().label("a");
-    let f = fetch().unwrap();
+    let _ = hd.new().label("a");
+    let f = hd.fetch().unwrap();
     assert_eq!(
         f,
         vec![Progress {
@@ -236,8 +238,1286 @@ fn reset_test() {
         }]
     );
 
-    reset();
+    hd.reset();
 
-    let f = fetch();
+    let f = hd.fetch();
     assert_eq!(f, Some(vec![]));
 }
+
+#[test]
+fn guard_test() {
+    let hd = init();
+
+    let a = hd.new_guarded().label("a");
+    let b = hd.new_root_guarded().label("b");
+    let c = hd.new_root_guarded().label("c").on_drop(OnDrop::Close);
+    let d = hd.new_root_guarded().label("d").on_drop(OnDrop::Finish);
+
+    b.finish();
+    drop(a);
+    drop(c);
+    drop(d);
+
+    let f = reset_rems(hd.fetch().unwrap());
+    assert_eq!(
+        f.into_iter()
+            .map(|x| (x.report.label, x.report.state))
+            .collect::<Vec<_>>(),
+        vec![
+            ("a".into(), State::Abandoned),
+            ("b".into(), State::Completed { duration: 1. }),
+            ("d".into(), State::Completed { duration: 1. }),
+        ]
+    );
+}
+
+#[test]
+fn cancel_report_test() {
+    let hd = init();
+
+    let a = hd.new_root().label("a");
+    let b = hd.new_with_parent(a.id()).label("b");
+    let c = hd.new_with_parent(b.id()).label("c");
+    let d = hd.new_root().label("d");
+
+    b.cancel();
+    assert!(b.cancelled());
+
+    // sync with the consumer loop, which propagates the cancellation
+    let _ = hd.fetch();
+
+    assert!(!a.cancelled());
+    assert!(b.cancelled());
+    assert!(c.cancelled());
+    assert!(!d.cancelled());
+    assert_eq!(hd.cancelled(), Some(false));
+
+    // new reports under a cancelled parent are also cancelled
+    let e = hd.new_with_parent(c.id());
+    let _ = hd.fetch();
+    assert!(e.cancelled());
+
+    let f = hd.fetch().unwrap();
+    assert_eq!(f[0].report.state, State::default());
+    assert_eq!(f[0].children[0].report.state, State::Cancelled);
+    assert_eq!(f[0].children[0].children[0].report.state, State::Cancelled);
+    assert_eq!(f[1].report.state, State::default());
+
+    hd.cancel_report(a.id());
+    let _ = hd.fetch();
+    assert!(a.cancelled());
+    assert!(!d.cancelled());
+}
+
+#[test]
+fn cancel_token_reset_disable_test() {
+    let hd = init();
+
+    let a = hd.new();
+    let b = hd.new_root();
+    b.cancel();
+    hd.cancel();
+    assert!(a.cancelled());
+    assert!(b.cancelled());
+
+    hd.reset();
+    let _ = hd.fetch();
+    assert!(!a.cancelled());
+    assert!(!b.cancelled());
+    assert_eq!(hd.cancelled(), Some(false));
+
+    let c = hd.new();
+    c.cancel();
+    assert!(c.cancelled());
+
+    // a copy of the reporter shares the report's token
+    let d = c;
+    assert!(d.cancelled());
+    // a reinitialised loop does not track the old reports
+    hd.init(consumers::Noop::default());
+    assert!(!d.cancelled());
+    d.cancel();
+    assert!(!d.cancelled());
+
+    hd.disable();
+    assert!(!c.cancelled());
+    assert_eq!(hd.cancelled(), None);
+}
+
+#[test]
+fn id_allocation_test() {
+    let hd = init();
+
+    let a = hd.new();
+    let b = hd.new();
+    assert_ne!(a.id(), b.id());
+
+    // a new loop does not reuse ids
+    hd.init(consumers::Noop(Duration::ZERO));
+    let c = hd.new();
+    assert_ne!(a.id(), c.id());
+    assert_ne!(b.id(), c.id());
+
+    // the epoch wraps around, skipping the epochs of active loops
+    let epoch = c.id() >> instance::COUNTER_BITS;
+    instance::EPOCH.store(epoch, std::sync::atomic::Ordering::Relaxed);
+    let other = init();
+    let d = other.new();
+    assert_ne!(d.id() >> instance::COUNTER_BITS, epoch);
+
+    c.label("c"); // still reaches its own loop
+    assert_eq!(hd.fetch().unwrap()[0].report.label, "c");
+    assert!(other.fetch().unwrap()[0].report.label.is_empty());
+}
+
+#[test]
+fn instances_test() {
+    let a = init();
+    let b = init();
+
+    let _ = a.new().label("a");
+    let _ = b.new().label("b");
+    a.cancel();
+
+    let f = a.fetch().unwrap();
+    assert_eq!(f.len(), 1);
+    assert_eq!(f[0].report.label, "a");
+    assert_eq!(a.cancelled(), Some(true));
+
+    let f = b.fetch().unwrap();
+    assert_eq!(f.len(), 1);
+    assert_eq!(f[0].report.label, "b");
+    assert_eq!(b.cancelled(), Some(false));
+}
+
+#[test]
+fn scope_test() {
+    let hd = init();
+
+    let a = hd.new_root().label("a");
+    let b = hd.new_root().label("b");
+
+    scope(&a, || {
+        let _ = hd.new().label("a1");
+
+        let _b = b.enter();
+        let _ = hd.new().label("b1");
+    });
+
+    // scoped to another instance, so does not apply
+    let other = init();
+    let _x = other.new().enter();
+    let _ = hd.new_root().label("c");
+    let _ = hd.new().label("c1");
+
+    let f = hd.fetch().unwrap();
+    let labels = |x: &Progress| {
+        x.children
+            .iter()
+            .map(|x| x.report.label.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(labels(&f[0]), vec!["a1"]);
+    assert_eq!(labels(&f[1]), vec!["b1"]);
+    assert_eq!(labels(&f[2]), vec!["c1"]);
+
+    // scope is not inherited by other threads
+    let _a = a.enter();
+    let hd2 = hd.clone();
+    std::thread::spawn(move || {
+        let _ = hd2.new_root().label("d");
+        let _ = hd2.new().label("d1");
+    })
+    .join()
+    .unwrap();
+
+    let f = hd.fetch().unwrap();
+    assert_eq!(labels(&f[0]), vec!["a1"]);
+    assert_eq!(labels(&f[3]), vec!["d1"]);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(flavor = "multi_thread")]
+async fn future_with_progress_test() {
+    let hd = init();
+
+    let hd2 = hd.clone();
+    let inner = async move {
+        tokio::task::yield_now().await;
+        hd2.new().label("nested").finish();
+    }
+    .with_progress_on(&hd, "inner");
+
+    inner.with_progress_on(&hd, "outer").await;
+
+    let dropped = std::future::pending::<()>().with_progress_on(&hd, "dropped");
+    let _ = tokio::time::timeout(Duration::from_millis(10), dropped).await;
+
+    let f = reset_rems(hd.fetch().unwrap());
+    assert_eq!(f[0].report.label, "outer");
+    assert_eq!(f[0].report.state, State::Completed { duration: 1. });
+    assert_eq!(f[0].children[0].report.label, "inner");
+    assert_eq!(f[0].children[0].children[0].report.label, "nested");
+    assert_eq!(f[1].report.label, "dropped");
+    assert_eq!(f[1].report.state, State::Cancelled);
+}
+
+#[test]
+fn iter_progress_test() {
+    let hd = init();
+
+    let xs = (0..10).progress_on(&hd, "a").collect::<Vec<_>>();
+    assert_eq!(xs.len(), 10);
+
+    let parent = hd.new_root().label("parent");
+    let mut it = (0..10)
+        .progress_on(&hd, "b")
+        .with_parent(parent.id())
+        .fmt_as_bytes(true)
+        .cancellable(true);
+    it.next();
+    it.next();
+    it.tx().unwrap().cancel();
+    assert_eq!(it.next(), None);
+    drop(it);
+
+    let mut it = (0..10)
+        .filter(|_| true)
+        .progress_on(&hd, "c")
+        .with_parent(parent.id());
+    it.next();
+    drop(it);
+
+    let f = reset_rems(hd.fetch().unwrap());
+    assert_eq!(f[0].report.label, "a");
+    assert_eq!(f[0].report.state, State::Completed { duration: 1. });
+    assert_eq!(f[1].report.label, "parent");
+    assert_eq!(f[1].children[0].report.label, "b");
+    assert_eq!(f[1].children[0].report.state, State::Cancelled);
+    let c = &f[1].children[1].report;
+    assert_eq!(c.label, "c");
+    assert_eq!(c.state, State::Abandoned);
+}
+
+#[test]
+fn iter_progress_len_test() {
+    let hd = init();
+
+    let mut it = (0..10).progress_on(&hd, "a");
+    it.nth(2);
+    let mut it2 = (0..10).filter(|_| true).progress_on(&hd, "b");
+    it2.next();
+
+    let f = reset_rems(hd.fetch().unwrap());
+    assert_eq!(
+        f[0].report.state,
+        State::InProgress {
+            len: Some(10),
+            pos: 3,
+            bytes: false,
+            remaining: 1.
+        }
+    );
+    assert_eq!(
+        f[0].children[0].report.state,
+        State::InProgress {
+            len: None,
+            pos: 1,
+            bytes: false,
+            remaining: 1.
+        }
+    );
+}
+
+#[test]
+fn io_progress_test() {
+    use std::io::{BufRead, Cursor, Read, Write};
+
+    let hd = init();
+    let data = vec![1u8; 100];
+
+    let mut r = io::ProgressReader::new(data.as_slice(), hd.new_root().label("read"))
+        .with_len(data.len() as u64);
+    let mut buf = [0u8; 40];
+    r.read_exact(&mut buf).unwrap();
+
+    let mut r2 = io::ProgressReader::new(Cursor::new(&data), hd.new_root().label("buf"))
+        .with_seek_len()
+        .unwrap();
+    let n = r2.fill_buf().unwrap().len();
+    r2.consume(n - 10);
+
+    let mut w = io::ProgressWriter::new(Vec::new(), hd.new_root().label("write"));
+    w.write_all(&data[..30]).unwrap();
+
+    let mut out = Vec::new();
+    io::copy_with_progress(&mut data.as_slice(), &mut out, hd.new_root().label("copy")).unwrap();
+    assert_eq!(out, data);
+
+    let pos = |x: &Progress| match x.report.state {
+        State::InProgress {
+            len, pos, bytes, ..
+        } => (len, pos, bytes),
+        _ => panic!("not in progress"),
+    };
+
+    let f = reset_rems(hd.fetch().unwrap());
+    assert_eq!(pos(&f[0]), (Some(100), 40, true));
+    assert_eq!(pos(&f[1]), (Some(100), 90, true));
+    assert_eq!(pos(&f[2]), (None, 30, true));
+    assert_eq!(f[3].report.state, State::Completed { duration: 1. });
+
+    r.read_to_end(&mut Vec::new()).unwrap();
+    r2.read_to_end(&mut Vec::new()).unwrap();
+    assert_eq!(w.finish().len(), 30);
+
+    let f = reset_rems(hd.fetch().unwrap());
+    assert!(f
+        .iter()
+        .all(|x| x.report.state == State::Completed { duration: 1. }));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn async_io_progress_test() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let hd = init();
+    let data = vec![1u8; 100];
+
+    let mut r =
+        io::AsyncProgressReader::new(data.as_slice(), hd.new_root().label("read")).with_len(100);
+    let mut buf = [0u8; 40];
+    r.read_exact(&mut buf).await.unwrap();
+
+    let mut w = io::AsyncProgressWriter::new(Vec::new(), hd.new_root().label("write"));
+    w.write_all(&data[..30]).await.unwrap();
+
+    let f = reset_rems(hd.fetch().unwrap());
+    assert_eq!(
+        f[0].report.state,
+        State::InProgress {
+            len: Some(100),
+            pos: 40,
+            bytes: true,
+            remaining: 1.
+        }
+    );
+    assert_eq!(
+        f[1].report.state,
+        State::InProgress {
+            len: None,
+            pos: 30,
+            bytes: true,
+            remaining: 1.
+        }
+    );
+
+    w.tx().unwrap().cancel();
+    let e = w.write_all(&data).await.unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::Interrupted);
+
+    r.read_to_end(&mut Vec::new()).await.unwrap();
+
+    let f = reset_rems(hd.fetch().unwrap());
+    assert_eq!(f[0].report.state, State::Completed { duration: 1. });
+    assert_eq!(f[1].report.state, State::Cancelled);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn progress_stream_test() {
+    use futures_core::Stream;
+    use std::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    struct Count(u8);
+    impl Stream for Count {
+        type Item = u8;
+        fn poll_next(mut self: std::pin::Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<u8>> {
+            self.0 += 1;
+            Poll::Ready((self.0 <= 5).then_some(self.0))
+        }
+    }
+
+    let hd = init();
+    let mut s = pin!(future::ProgressStream::new(Count(0), hd.new().label("s")).with_len(5));
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut n = 0;
+    while let Poll::Ready(Some(_)) = s.as_mut().poll_next(&mut cx) {
+        n += 1;
+    }
+    assert_eq!(n, 5);
+
+    let f = reset_rems(hd.fetch().unwrap());
+    assert_eq!(f[0].report.state, State::Completed { duration: 1. });
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn par_progress_test() {
+    use par::ParallelProgressExt;
+    use rayon::prelude::*;
+
+    let hd = init();
+
+    let sum: u64 = (0..1000_u64)
+        .into_par_iter()
+        .progress_on(&hd, "sum")
+        .show_workers(true)
+        .sum();
+    assert_eq!(sum, 499500);
+
+    let f = reset_rems(hd.fetch().unwrap());
+    assert_eq!(f[0].report.label, "sum");
+    assert_eq!(f[0].report.state, State::Completed { duration: 1. });
+    assert!(f[0].children.is_empty()); // workers are closed
+
+    hd.cancel();
+    let n = (0..1000_u64)
+        .into_par_iter()
+        .progress_on(&hd, "cancelled")
+        .cancellable(true)
+        .count();
+    assert_eq!(n, 0);
+
+    // cancellation is checked with each batch of items
+    let hd = init();
+    let n = (0..100_000_u32)
+        .into_par_iter()
+        .with_min_len(100_000)
+        .progress_on(&hd, "stopped")
+        .cancellable(true)
+        .inspect(|&x| {
+            if x == 10 {
+                hd.cancel();
+            }
+        })
+        .count();
+    assert!(n < 1000);
+}
+
+#[test]
+fn coalesced_pos_test() {
+    let hd = init();
+
+    let a = hd.new().label("a");
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                for _ in 0..10_000 {
+                    a.inc();
+                }
+            });
+        }
+    });
+
+    let pos = || match hd.fetch().unwrap()[0].report.state {
+        State::InProgress { pos, .. } => pos,
+        _ => panic!("not in progress"),
+    };
+    assert_eq!(pos(), 40_000);
+
+    a.set_pos(5_u8);
+    assert_eq!(pos(), 5);
+    a.inc_by(3_u8);
+    assert_eq!(pos(), 8);
+}
+
+#[test]
+fn pos_no_wait_test() {
+    let (entered, entered_rx) = flume::unbounded();
+    let (release_tx, release) = flume::unbounded();
+
+    let hd = Howudoin::default();
+    let handle = Builder::new()
+        .capacity(1)
+        .init_on(&hd, Blocker { entered, release });
+
+    let a = hd.new_root();
+    entered_rx.recv().unwrap(); // consumer is blocked
+    a.inc(); // notifies the loop, filling the channel
+
+    // the position updates neither wait on the loop nor go through the channel
+    let (done_tx, done) = flume::bounded(1);
+    std::thread::spawn(move || {
+        for _ in 0..100_000 {
+            a.inc();
+        }
+        done_tx.send(()).ok();
+    });
+    done.recv_timeout(Duration::from_secs(5))
+        .expect("position updates waited on the consumer loop");
+
+    drop(release_tx);
+    match hd.fetch().unwrap()[0].report.state {
+        State::InProgress { pos, .. } => assert_eq!(pos, 100_001),
+        _ => panic!("not in progress"),
+    }
+
+    hd.disable();
+    handle.into_join_handle().join().unwrap();
+}
+
+/// A consumer which blocks on each report until released.
+struct Blocker {
+    entered: flume::Sender<()>,
+    release: flume::Receiver<()>,
+}
+
+impl Consume for Blocker {
+    fn debounce(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn rpt(&mut self, _: &Report, _: Id, _: Option<Id>, _: &Controller) {
+        self.entered.send(()).ok();
+        self.release.recv().ok();
+    }
+}
+
+#[test]
+fn builder_test() {
+    let (entered, entered_rx) = flume::unbounded();
+    let (release_tx, release) = flume::unbounded();
+
+    let hd = Howudoin::default();
+    let handle = Builder::new()
+        .capacity(1)
+        .backpressure(Backpressure::DropNewest)
+        .thread_name("howudoin-test")
+        .init_on(&hd, Blocker { entered, release });
+    assert_eq!(handle.thread().name(), Some("howudoin-test"));
+
+    hd.new_root();
+    entered_rx.recv().unwrap(); // consumer is blocked
+
+    let b = hd.new_root(); // fills the channel
+    let c = hd.new_root(); // dropped
+    assert_ne!(b.id(), 0);
+    assert_eq!(c.id(), 0);
+
+    // cancelling is not dropped, it waits for space
+    let cancel = std::thread::spawn(move || b.cancel().id());
+
+    drop(release_tx);
+    cancel.join().unwrap();
+    let f = hd.fetch().unwrap();
+    assert_eq!(f.len(), 2);
+    assert_eq!(f[1].report.state, State::Cancelled);
+
+    hd.disable();
+    handle.into_join_handle().join().unwrap();
+}
+
+#[test]
+fn drop_coalescible_test() {
+    let (entered, entered_rx) = flume::unbounded();
+    let (release_tx, release) = flume::unbounded();
+
+    let hd = Howudoin::default();
+    let handle = Builder::new()
+        .capacity(1)
+        .backpressure(Backpressure::DropCoalescible)
+        .init_on(&hd, Blocker { entered, release });
+
+    let a = hd.new_root();
+    entered_rx.recv().unwrap(); // consumer is blocked
+
+    a.label("a"); // fills the channel
+    a.inc(); // the notification is dropped
+
+    let release = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        drop(release_tx);
+    });
+
+    a.desc("desc"); // not dropped, it waits for space
+    a.inc_by(4_u8);
+    release.join().unwrap();
+
+    let f = hd.fetch().unwrap();
+    assert_eq!(f[0].report.label, "a");
+    assert_eq!(f[0].report.desc, "desc");
+    match f[0].report.state {
+        State::InProgress { pos, .. } => assert_eq!(pos, 5),
+        _ => panic!("not in progress"),
+    }
+
+    hd.disable();
+    handle.into_join_handle().join().unwrap();
+}
+
+/// A consumer which records the reports and hooks it is invoked with.
+#[derive(Default, Clone)]
+struct Recorder {
+    debounce: Duration,
+    rpts: Arc<parking_lot::Mutex<Vec<(Id, Report)>>>,
+    hooks: Arc<parking_lot::Mutex<Vec<&'static str>>>,
+    events: Arc<parking_lot::Mutex<Vec<Event>>>,
+}
+
+impl Consume for Recorder {
+    fn debounce(&self) -> Duration {
+        self.debounce
+    }
+
+    fn rpt(&mut self, report: &Report, id: Id, _: Option<Id>, _: &Controller) {
+        self.rpts.lock().push((id, report.clone()));
+    }
+
+    fn closed(&mut self, _: Id) {
+        self.hooks.lock().push("closed");
+    }
+
+    fn event(&mut self, event: &Event, _: &Controller) {
+        self.events.lock().push(event.clone());
+    }
+
+    fn on_start(&mut self) {
+        self.hooks.lock().push("start");
+    }
+
+    fn on_tick(&mut self, _: &Controller) {
+        self.hooks.lock().push("tick");
+    }
+
+    fn on_reset(&mut self, _: &Controller) {
+        self.hooks.lock().push("reset");
+    }
+
+    fn on_shutdown(&mut self, _: &Controller) {
+        self.hooks.lock().push("shutdown");
+    }
+}
+
+#[test]
+fn shutdown_test() {
+    let rec = Recorder {
+        debounce: Duration::from_secs(60),
+        ..Default::default()
+    };
+
+    let hd = Howudoin::default();
+    let guard = hd.init_guarded(rec.clone());
+
+    let a = hd.new().label("a").set_len(10);
+    a.inc_by(10_u8);
+    a.finish();
+
+    drop(guard); // shutdown before the debounce elapses
+    assert_eq!(*rec.hooks.lock(), ["start", "shutdown"]);
+    assert_eq!(hd.cancelled(), None);
+
+    let rpts = rec.rpts.lock();
+    assert_eq!(rpts.len(), 1);
+    let (id, rpt) = &rpts[0];
+    assert_eq!(*id, a.id());
+    assert_eq!(rpt.label, "a");
+    assert!(matches!(rpt.state, State::Completed { .. }));
+
+    // shutting down without a loop is a no-op
+    assert!(hd.shutdown());
+}
+
+#[test]
+fn lifecycle_hooks_test() {
+    let rec = Recorder {
+        debounce: Duration::from_millis(10),
+        ..Default::default()
+    };
+
+    let hd = Howudoin::default();
+    hd.init(rec.clone());
+
+    // ticks occur without changes
+    std::thread::sleep(Duration::from_millis(100));
+    let ticks = rec.hooks.lock().iter().filter(|x| **x == "tick").count();
+    assert!(ticks > 2, "only {ticks} ticks");
+    assert!(rec.rpts.lock().is_empty());
+
+    let _a = hd.new_root();
+    hd.fetch();
+    rec.hooks.lock().clear();
+
+    hd.reset();
+    hd.fetch();
+    let hooks = rec.hooks.lock().clone();
+    let hooks = hooks.iter().filter(|x| **x != "tick").collect::<Vec<_>>();
+    assert_eq!(hooks, [&"closed", &"reset"]);
+
+    assert!(hd.shutdown());
+    assert_eq!(rec.hooks.lock().last(), Some(&"shutdown"));
+}
+
+#[test]
+fn batch_test() {
+    type Batch = Vec<(Id, bool)>;
+
+    #[derive(Clone, Default)]
+    struct Batches(Arc<parking_lot::Mutex<Vec<Batch>>>);
+
+    impl Consume for Batches {
+        fn debounce(&self) -> Duration {
+            Duration::from_secs(60)
+        }
+
+        fn rpt(&mut self, _: &Report, _: Id, _: Option<Id>, _: &Controller) {
+            panic!("rpt should not be invoked");
+        }
+
+        fn batch(&mut self, changes: &[Change], _: &Controller) {
+            let x = changes
+                .iter()
+                .map(|x| match x {
+                    Change::Updated { id, .. } => (*id, true),
+                    Change::Closed(id) => (*id, false),
+                })
+                .collect();
+            self.0.lock().push(x);
+        }
+    }
+
+    let batches = Batches::default();
+    let hd = Howudoin::default();
+    hd.init(batches.clone());
+
+    let a = hd.new_root();
+    let b = hd.new_root();
+    let c = hd.new_with_parent(a.id());
+    b.close();
+    hd.shutdown();
+
+    assert_eq!(
+        *batches.0.lock(),
+        [vec![(a.id(), true), (b.id(), false), (c.id(), true)]]
+    );
+
+    // the default implementation forwards to rpt and closed
+    let rec = Recorder {
+        debounce: Duration::from_secs(60),
+        ..Default::default()
+    };
+    let hd = Howudoin::default();
+    hd.init(rec.clone());
+    let a = hd.new_root();
+    hd.new_root().close();
+    hd.shutdown();
+
+    assert_eq!(rec.rpts.lock().len(), 1);
+    assert_eq!(rec.rpts.lock()[0].0, a.id());
+    assert!(rec.hooks.lock().contains(&"closed"));
+}
+
+#[test]
+fn event_test() {
+    let rec = Recorder {
+        debounce: Duration::from_secs(60),
+        ..Default::default()
+    };
+
+    let hd = Howudoin::default();
+    hd.init(rec.clone());
+
+    let a = hd.new_root().label("a");
+    let b = hd.new_with_parent(a.id());
+    a.desc("desc").inc_by(3_u8);
+    hd.fetch(); // samples the position
+    b.add_err("oh no").inc_by(5_u8).finish(); // progress is delivered before finishing
+    b.close();
+    b.close(); // does not exist
+    hd.cancel_report(a.id());
+    hd.fetch(); // the cancellation is processed before the increment
+    a.inc();
+    hd.fetch(); // a is no longer in progress
+    hd.cancel();
+    hd.reset();
+    hd.shutdown();
+
+    let (a, b) = (a.id(), b.id());
+    assert_eq!(
+        *rec.events.lock(),
+        [
+            Event::Created {
+                id: a,
+                parent: None
+            },
+            Event::LabelChanged {
+                id: a,
+                label: "a".into()
+            },
+            Event::Created {
+                id: b,
+                parent: Some(a)
+            },
+            Event::DescChanged {
+                id: a,
+                desc: "desc".into()
+            },
+            Event::Progressed { id: a, pos: 3 },
+            Event::MessageAdded {
+                id: b,
+                message: Message {
+                    severity: Severity::Error,
+                    msg: "oh no".into()
+                }
+            },
+            Event::Progressed { id: b, pos: 5 },
+            Event::Finished(b),
+            Event::Closed(b),
+            Event::Cancelled(Some(a)),
+            Event::Cancelled(None),
+            Event::Reset,
+        ]
+    );
+}
+
+#[test]
+fn subscribe_test() {
+    let hd = Howudoin::default();
+    assert!(hd.subscribe().is_none());
+
+    hd.init(consumers::Noop(Duration::from_secs(60)));
+    let all = hd.subscribe().unwrap();
+    let small = hd.subscribe_with_capacity(2).unwrap();
+    drop(hd.subscribe()); // dropped subscriptions are removed
+
+    let a = hd.new_root().label("a");
+    a.finish();
+    hd.fetch();
+
+    assert_eq!(
+        small.drain().collect::<Vec<_>>(),
+        [
+            Event::Created {
+                id: a.id(),
+                parent: None
+            },
+            Event::LabelChanged {
+                id: a.id(),
+                label: "a".into()
+            },
+        ]
+    );
+    assert_eq!(small.lagged(), 1);
+    assert_eq!(small.lagged(), 0);
+
+    assert!(!all.is_closed());
+    hd.shutdown();
+    assert!(all.is_closed());
+    assert_eq!(all.iter().count(), 3);
+    assert_eq!(all.lagged(), 0);
+    assert_eq!(all.recv(), None);
+}
+
+#[test]
+fn fanout_test() {
+    let fast = Recorder::default();
+    let mid = Recorder {
+        debounce: Duration::from_millis(20),
+        ..Default::default()
+    };
+    let slow = Recorder {
+        debounce: Duration::from_secs(60),
+        ..Default::default()
+    };
+
+    let hd = Howudoin::default();
+    let fanout = consumers::Fanout::new()
+        .with(mid.clone())
+        .with(slow.clone());
+    let tee = consumers::Tee::new(fast.clone(), fanout);
+    assert_eq!(tee.debounce(), Duration::from_millis(20));
+    hd.init(tee);
+
+    let a = hd.new_root().label("a");
+    std::thread::sleep(Duration::from_millis(100));
+
+    // debounces are independent, and a zero debounce does not stall the others
+    assert!(!fast.rpts.lock().is_empty());
+    assert_eq!(mid.rpts.lock().last().unwrap().1.label, "a");
+    assert!(slow.rpts.lock().is_empty());
+    assert_eq!(fast.events.lock().len(), 2);
+    assert_eq!(slow.events.lock().len(), 2);
+
+    hd.shutdown();
+    let rpts = slow.rpts.lock();
+    assert_eq!(rpts.len(), 1);
+    assert_eq!(rpts[0].0, a.id());
+    assert_eq!(rpts[0].1.label, "a");
+    assert_eq!(*slow.hooks.lock(), ["start", "shutdown"]);
+
+    // tuples of consumers are also independent
+    let hd = Howudoin::default();
+    let mid = Recorder {
+        debounce: Duration::from_millis(20),
+        ..Default::default()
+    };
+    let slow = Recorder {
+        debounce: Duration::from_secs(60),
+        ..Default::default()
+    };
+    let boxed: Box<dyn Consume + Send> = Box::new(slow.clone());
+    hd.init((mid.clone(), boxed));
+    hd.new_root();
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(mid.rpts.lock().len(), 1);
+    assert!(slow.rpts.lock().is_empty());
+    hd.shutdown();
+    assert_eq!(slow.rpts.lock().len(), 1);
+
+    // the pending changes are delivered as they were received
+    let hd = Howudoin::default();
+    let slow = Recorder {
+        debounce: Duration::from_secs(60),
+        ..Default::default()
+    };
+    let consumer = (mid.clone(), slow.clone())
+        .into_consumer()
+        .map_report(|rpt, _| rpt.label = rpt.label.to_uppercase());
+    hd.init(consumer);
+    hd.new_root().label("a");
+    hd.shutdown();
+    let rpts = slow.rpts.lock();
+    assert_eq!(rpts.len(), 1);
+    assert_eq!(rpts[0].1.label, "A");
+}
+
+#[test]
+fn consume_ext_test() {
+    let seen = Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let seen_ = seen.clone();
+    let consumer = consumers::FnConsumer::new(move |change, _| {
+        seen_.lock().push(match change {
+            Change::Updated { report, .. } => report.label.clone(),
+            Change::Closed(_) => "closed".to_string(),
+        })
+    })
+    .with_debounce(Duration::ZERO)
+    .map_report(|rpt, _| rpt.label = rpt.label.to_uppercase())
+    .filter(|rpt, _, _| rpt.label != "hidden")
+    .max_depth(1);
+
+    let throttled = consumers::Noop(Duration::ZERO).throttle(Duration::from_secs(1));
+    assert_eq!(throttled.debounce(), Duration::from_secs(1));
+
+    let hd = Howudoin::default();
+    hd.init(consumer);
+
+    let a = hd.new_root().label("a");
+    let b = hd.new_with_parent(a.id()).label("b");
+    let _c = hd.new_with_parent(b.id()).label("c"); // too deep
+    let _d = hd.new_root().label("hidden"); // delivered unlabelled, then hidden
+    a.label("hidden");
+    hd.shutdown();
+
+    assert_eq!(*seen.lock(), ["", "A", "", "B", "", "closed", "closed"]);
+
+    // events are filtered as they are emitted
+    let rec = Recorder::default();
+    let hd = Howudoin::default();
+    hd.init(rec.clone().max_depth(0));
+    let a = hd.new_root().label("a");
+    let b = hd.new_with_parent(a.id()).label("b"); // too deep
+    b.finish();
+    a.finish();
+    a.close();
+    hd.cancel();
+    hd.shutdown();
+
+    let a = a.id();
+    assert_eq!(
+        *rec.events.lock(),
+        [
+            Event::Created {
+                id: a,
+                parent: None
+            },
+            Event::LabelChanged {
+                id: a,
+                label: "a".into()
+            },
+            Event::Finished(a),
+            Event::Closed(a),
+            Event::Cancelled(None),
+        ]
+    );
+
+    // a report which is hidden before its first batch is never delivered
+    let rec = Recorder {
+        debounce: Duration::from_millis(30),
+        ..Default::default()
+    };
+    let hd = Howudoin::default();
+    hd.init(
+        rec.clone()
+            .filter(|rpt, _, _| !rpt.label.starts_with("internal")),
+    );
+    let a = hd.new_root().label("internal");
+    a.close();
+    hd.shutdown();
+
+    assert!(rec.rpts.lock().is_empty());
+    assert!(!rec.hooks.lock().contains(&"closed"));
+    assert_eq!(
+        *rec.events.lock(),
+        [
+            Event::Created {
+                id: a.id(),
+                parent: None
+            },
+            Event::Closed(a.id()),
+        ]
+    );
+}
+
+#[test]
+fn replace_consumer_test() {
+    let old = Recorder {
+        debounce: Duration::from_secs(60),
+        ..Default::default()
+    };
+    let new = Recorder::default();
+
+    let hd = Howudoin::default();
+    assert!(!hd.replace_consumer(new.clone()));
+    hd.init(old.clone());
+
+    let a = hd.new_root().label("a");
+    let b = hd.new_root().label("b");
+    let c = hd.new_with_parent(a.id()).label("c");
+    assert!(hd.replace_consumer(new.clone()));
+    b.finish();
+    hd.fetch();
+
+    // old consumer is finalised with its pending changes
+    assert_eq!(*old.hooks.lock(), ["start", "shutdown"]);
+    assert_eq!(old.rpts.lock().len(), 3);
+
+    // new consumer is replayed the tree, in tree order
+    assert_eq!(new.hooks.lock()[0], "start");
+    let ids = new.rpts.lock().iter().map(|x| x.0).collect::<Vec<_>>();
+    assert_eq!(ids, [a.id(), c.id(), b.id(), b.id()]);
+    assert_eq!(hd.fetch().unwrap().len(), 2);
+
+    hd.shutdown();
+    assert_eq!(old.hooks.lock().len(), 2);
+    assert_eq!(new.hooks.lock().last(), Some(&"shutdown"));
+}
+
+/// A consumer which panics on a report labelled `boom`.
+struct Panicker;
+
+impl Consume for Panicker {
+    fn debounce(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn rpt(&mut self, report: &Report, _: Id, _: Option<Id>, _: &Controller) {
+        if report.label == "boom" {
+            panic!("boom");
+        }
+    }
+}
+
+#[test]
+fn consumer_panic_test() {
+    let hd = Howudoin::default();
+    Builder::new().init_on(&hd, Panicker);
+    assert_eq!(hd.stats(), Some(Stats::default()));
+
+    let a = hd.new_root().label("boom");
+    let b = hd.new_with_parent(a.id());
+    hd.fetch();
+
+    // the loop is still alive
+    hd.cancel_report(a.id());
+    assert_eq!(hd.fetch().unwrap().len(), 1);
+    assert!(b.cancelled());
+
+    let stats = hd.stats().unwrap();
+    assert_eq!(stats.panics, 1);
+    assert_eq!(stats.last_panic.as_deref(), Some("boom"));
+    assert!(stats.failed);
+    assert!(!stats.fallback);
+    assert!(hd.shutdown());
+
+    // with a fallback consumer
+    let rec = Recorder::default();
+    let hd = Howudoin::default();
+    Builder::new().fallback(rec.clone()).init_on(&hd, Panicker);
+
+    let a = hd.new_root().label("boom");
+    hd.new_with_parent(a.id()).label("child");
+    hd.fetch();
+
+    let stats = hd.stats().unwrap();
+    assert_eq!(stats.panics, 1);
+    assert!(stats.fallback);
+    assert!(!stats.failed);
+
+    // fallback is delivered the tree
+    assert_eq!(rec.hooks.lock()[0], "start");
+    let labels = rec
+        .rpts
+        .lock()
+        .iter()
+        .map(|x| x.1.label.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(labels, ["boom", "", "child"]);
+}
+
+#[test]
+fn pump_test() {
+    let rec = Recorder::default();
+    let hd = Howudoin::default();
+    let mut pump = Builder::new().pump_on(&hd, rec.clone());
+
+    let a = hd.new_root().label("a");
+    assert!(rec.hooks.lock().is_empty());
+    assert!(pump.try_pump());
+    assert_eq!(rec.hooks.lock()[0], "start");
+    assert_eq!(rec.rpts.lock().len(), 1); // updates are delivered together
+    assert_eq!(rec.rpts.lock()[0].1.label, "a");
+
+    a.finish();
+    assert!(pump.pump(Duration::from_millis(10)));
+    assert!(matches!(
+        rec.rpts.lock()[1].1.state,
+        State::Completed { .. }
+    ));
+
+    hd.disable();
+    assert!(!pump.try_pump());
+    assert!(pump.is_finished());
+    assert!(!pump.pump(Duration::from_secs(60)));
+    assert_eq!(rec.hooks.lock().last(), Some(&"shutdown"));
+
+    // dropping the pump finalises the consumer
+    let rec = Recorder::default();
+    let pump = Builder::new().pump_on(&hd, rec.clone());
+    hd.new_root();
+    drop(pump);
+    assert_eq!(rec.rpts.lock().len(), 1);
+    assert_eq!(*rec.hooks.lock(), ["start", "shutdown"]);
+
+    // the consumer is invoked on the pumping thread, so it does not need to be `Send`
+    let seen = std::rc::Rc::new(std::cell::Cell::new(0));
+    let seen_ = seen.clone();
+    let consumer = consumers::FnConsumer::new(move |_, _| seen_.set(seen_.get() + 1));
+    let mut pump = Builder::new().pump_on(&hd, consumer.with_debounce(Duration::ZERO));
+    hd.new_root();
+    assert!(pump.try_pump());
+    assert_eq!(seen.get(), 1);
+
+    // a bounded channel would deadlock the pumping thread
+    let bounded = std::panic::catch_unwind(|| {
+        Builder::new()
+            .capacity(2)
+            .pump_on(&Howudoin::default(), consumers::Noop::default())
+    });
+    assert!(bounded.is_err());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(flavor = "current_thread")]
+async fn init_async_test() {
+    let rec = Recorder {
+        debounce: Duration::from_millis(5),
+        ..Default::default()
+    };
+    let hd = Howudoin::default();
+    let task = Builder::new().init_async_on(&hd, rec.clone());
+
+    let a = hd.new_root().label("a");
+    a.inc();
+    // the loop runs on this thread, so fetching must not block
+    let p = hd.fetch_async().await.unwrap();
+    assert_eq!(p.len(), 1);
+    assert_eq!(p[0].report.label, "a");
+
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(rec.rpts.lock().last().unwrap().1.label, "a");
+    assert!(rec.hooks.lock().contains(&"tick"));
+
+    let cancelled = tokio::spawn({
+        let hd = hd.clone();
+        async move { hd.cancelled_async().await }
+    });
+    tokio::task::yield_now().await;
+    hd.cancel();
+    assert!(cancelled.await.unwrap());
+
+    // disabling ends the waiters and the loop
+    hd.reset();
+    let waiter = tokio::spawn({
+        let hd = hd.clone();
+        async move { hd.cancelled_async().await }
+    });
+    tokio::task::yield_now().await;
+    hd.disable();
+    assert!(!waiter.await.unwrap());
+    task.await.unwrap();
+    assert_eq!(rec.hooks.lock().last(), Some(&"shutdown"));
+    assert!(hd.fetch_async().await.is_none());
+}
+
+#[test]
+fn controller_query_test() {
+    type Snapshot = (Vec<Id>, Vec<(Id, String)>, Vec<Id>, Option<usize>, bool);
+    let snap: Arc<parking_lot::Mutex<Option<Snapshot>>> = Default::default();
+    let snap_ = snap.clone();
+    let hd = Howudoin::default();
+    let mut pump = Builder::new().pump_on(
+        &hd,
+        consumers::FnConsumer::new(move |change, controller| {
+            let Change::Updated { id, .. } = change else {
+                return;
+            };
+            if controller.get(id).is_some_and(|x| x.label == "c") {
+                *snap_.lock() = Some((
+                    controller.roots().collect(),
+                    controller
+                        .iter()
+                        .map(|(id, x)| (id, x.label.clone()))
+                        .collect(),
+                    controller.ancestors(id).collect(),
+                    controller.depth(id),
+                    controller.is_cancelled(id),
+                ));
+                assert!(controller.elapsed(id).is_some());
+            }
+        })
+        .with_debounce(Duration::ZERO),
+    );
+
+    let a = hd.new_root().label("a");
+    let b = hd.new_with_parent(a.id()).label("b");
+    let c = hd.new_with_parent(b.id()).label("c");
+    let d = hd.new_root().label("d");
+    hd.cancel_report(b.id());
+    assert!(pump.try_pump());
+
+    let (roots, iter, ancestors, depth, cancelled) = snap.lock().take().unwrap();
+    assert_eq!(roots, [a.id(), d.id()]);
+    let labels = iter.iter().map(|x| x.1.as_str()).collect::<Vec<_>>();
+    assert_eq!(labels, ["a", "b", "c", "d"]);
+    assert_eq!(iter[2].0, c.id());
+    assert_eq!(ancestors, [b.id(), a.id()]);
+    assert_eq!(depth, Some(2));
+    assert!(cancelled);
+
+    // closing a parent makes its children roots
+    b.close();
+    assert!(pump.try_pump());
+
+    let (roots, iter, ancestors, depth, cancelled) = snap.lock().take().unwrap();
+    assert_eq!(roots, [a.id(), c.id(), d.id()]);
+    let labels = iter.iter().map(|x| x.1.as_str()).collect::<Vec<_>>();
+    assert_eq!(labels, ["a", "c", "d"]);
+    assert!(ancestors.is_empty());
+    assert_eq!(depth, Some(0));
+    assert!(cancelled);
+}
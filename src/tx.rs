@@ -1,10 +1,12 @@
 use super::*;
-use flume::{bounded, unbounded, Sender};
-use parking_lot::RwLock;
+use crate::instance::Shared;
+use std::{
+    ops::Deref,
+    ptr::{null_mut, slice_from_raw_parts_mut},
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering::*},
+};
 use Payload::*;
 
-static TX: StaticTx = StaticTx::none();
-
 /// Initialise the progress consumer loop.
 ///
 /// Progress reports are only sent through if an `init` call has previously been invoked.
@@ -13,30 +15,131 @@ static TX: StaticTx = StaticTx::none();
 /// ```rust
 /// howudoin::init(howudoin::consumers::Noop::default());
 /// ```
-pub fn init<C: Consume + Send + 'static>(consumer: C) {
-    let (tx, rx) = unbounded();
-    TX.set_tx(tx);
+pub fn init<C>(consumer: C)
+where
+    C: IntoConsumer,
+    C::Consumer: Send + 'static,
+{
+    Howudoin::global().init(consumer)
+}
 
-    std::thread::spawn(|| super::rx::spawn(rx, consumer));
+/// Initialise the progress consumer loop, returning a guard which [`shutdown`]s the loop when
+/// dropped.
+///
+/// # Example
+/// ```rust
+/// let _guard = howudoin::init_guarded(howudoin::consumers::Noop::default());
+///
+/// let rpt = howudoin::new().label("Progress");
+/// rpt.finish();
+/// // the finish is delivered to the consumer before exiting
+/// ```
+pub fn init_guarded<C>(consumer: C) -> ShutdownGuard
+where
+    C: IntoConsumer,
+    C::Consumer: Send + 'static,
+{
+    Howudoin::global().init_guarded(consumer)
+}
+
+/// Initialise the progress consumer loop as a tokio task. Requires `tokio` feature.
+///
+/// This is the counterpart of [`init`] for async applications: the consumer loop is spawned onto
+/// the current tokio runtime rather than a thread, with the debounce driven by a
+/// [`tokio::time::Interval`]. The consumer is invoked on the runtime's worker threads, so its
+/// callbacks should not block.
+///
+/// # Panics
+/// Panics if called outside of a tokio runtime.
+///
+/// Note that [`shutdown`] blocks while waiting for the loop to exit. From within the runtime,
+/// [`disable`] the loop and await the task returned by [`Builder::init_async`] instead.
+///
+/// # Example
+/// ```rust
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// howudoin::init_async(howudoin::consumers::Noop::default());
+///
+/// let rpt = howudoin::new().label("Progress");
+/// rpt.finish();
+///
+/// let progress = howudoin::fetch_async().await;
+/// # }
+/// ```
+#[cfg(feature = "tokio")]
+pub fn init_async<C>(consumer: C)
+where
+    C: IntoConsumer,
+    C::Consumer: Send + 'static,
+{
+    Howudoin::global().init_async(consumer)
+}
+
+/// Replace the consumer of the running consumer loop, keeping the progress tree.
+///
+/// The old consumer is delivered its pending changes and finalised with
+/// [`Consume::on_shutdown`], then the new consumer is started and delivered the current tree.
+/// Unlike calling [`init`] again, the tracked reports are not discarded.
+/// Returns `false` if the consumer loop has not been [`init`]ialised.
+///
+/// # Example
+/// ```rust
+/// howudoin::init(howudoin::consumers::Noop::default());
+/// let rpt = howudoin::new().label("Progress");
+///
+/// // later, once it is known that stdout is interactive
+/// # #[cfg(feature = "term-line")]
+/// howudoin::replace_consumer(howudoin::consumers::TermLine::new());
+/// ```
+pub fn replace_consumer<C>(consumer: C) -> bool
+where
+    C: IntoConsumer,
+    C::Consumer: Send + 'static,
+{
+    Howudoin::global().replace_consumer(consumer)
 }
 
 /// Disable the progress reporting consumer loop, terminating the sender side.
 ///
 /// This is effectively the opposite of [`init`].
+/// The consumer loop delivers the pending changes and exits in the background, use [`shutdown`]
+/// to wait for it.
 pub fn disable() {
-    TX.disable()
+    Howudoin::global().disable()
+}
+
+/// Shutdown the progress consumer loop, waiting for it to exit.
+///
+/// The sender side is terminated as with [`disable`], then the consumer loop drains the channel,
+/// delivers the pending changes to the consumer, and invokes [`Consume::on_shutdown`].
+/// This waits for at most the [`Builder::shutdown_timeout`], returning `false` if the loop did not
+/// exit in time.
+///
+/// # Example
+/// ```rust
+/// howudoin::init(howudoin::consumers::Noop::default());
+///
+/// let rpt = howudoin::new().label("Progress");
+/// rpt.finish();
+///
+/// howudoin::shutdown(); // the finish is delivered to the consumer
+/// ```
+pub fn shutdown() -> bool {
+    Howudoin::global().shutdown()
 }
 
 /// Generate a new progress reporter.
 ///
-/// The reporter's parent will be the _last_ report generated (if one exists).
+/// The reporter's parent will be the report of the innermost [`scope`] on the current thread.
+/// If no scope is active, the parent will be the _last_ report generated (if one exists).
 ///
 /// # Example
 /// ```rust
 /// let rpt = howudoin::new().label("Progress");
 /// ```
 pub fn new() -> Tx {
-    new_(|x| AddReport(None, x))
+    Howudoin::global().new()
 }
 
 /// Generate a new progress reporter under a parent.
@@ -47,7 +150,7 @@ pub fn new() -> Tx {
 /// let child = howudoin::new_with_parent(parent.id());
 /// ```
 pub fn new_with_parent(parent: Id) -> Tx {
-    new_(|x| AddReport(Some(parent), x))
+    Howudoin::global().new_with_parent(parent)
 }
 
 /// Generate a new progress reporter at the root level.
@@ -57,15 +160,42 @@ pub fn new_with_parent(parent: Id) -> Tx {
 /// let rpt = howudoin::new_root().label("Progress");
 /// ```
 pub fn new_root() -> Tx {
-    new_(AddRootReport)
+    Howudoin::global().new_root()
+}
+
+/// Generate a new progress reporter, wrapped in a [`ReportGuard`].
+///
+/// The reporter's parent is chosen as with [`new`].
+/// If the guard is dropped before it is finished, the report is marked as
+/// [`State::Abandoned`](report::State::Abandoned).
+///
+/// # Example
+/// ```rust
+/// let rpt = howudoin::new_guarded().label("Progress");
+/// ```
+pub fn new_guarded() -> ReportGuard {
+    Howudoin::global().new_guarded()
 }
 
-fn new_<F: FnOnce(Sender<Id>) -> Payload>(f: F) -> Tx {
-    let (tx, rx) = bounded(1);
-    TX.send(|| f(tx));
-    let id = rx.recv_timeout(Duration::from_millis(500)).unwrap_or(0);
+/// Generate a new progress reporter under a parent, wrapped in a [`ReportGuard`].
+///
+/// # Example
+/// ```rust
+/// let parent = howudoin::new_guarded().label("Parent");
+/// let child = howudoin::new_with_parent_guarded(parent.id());
+/// ```
+pub fn new_with_parent_guarded(parent: Id) -> ReportGuard {
+    Howudoin::global().new_with_parent_guarded(parent)
+}
 
-    Tx { id }
+/// Generate a new progress reporter at the root level, wrapped in a [`ReportGuard`].
+///
+/// # Example
+/// ```rust
+/// let rpt = howudoin::new_root_guarded().label("Progress");
+/// ```
+pub fn new_root_guarded() -> ReportGuard {
+    Howudoin::global().new_root_guarded()
 }
 
 /// Fetch the progress report tree.
@@ -83,23 +213,112 @@ fn new_<F: FnOnce(Sender<Id>) -> Payload>(f: F) -> Tx {
 /// let progress = howudoin::fetch();
 /// ```
 pub fn fetch() -> Option<Vec<report::Progress>> {
-    let (tx, rx) = bounded(1);
-    TX.send(|| Fetch(tx));
-    rx.recv_timeout(Duration::from_millis(500)).ok()
+    Howudoin::global().fetch()
+}
+
+/// Fetch the progress report tree, awaiting the reply. Requires `tokio` feature.
+///
+/// Unlike [`fetch`], this does not block the thread while waiting on the consumer loop, so it
+/// can be used from a tokio runtime, including a single-threaded one running the loop with
+/// [`init_async`].
+/// Waits for at most the [`Builder::fetch_timeout`] to send the request, and again for the reply.
+#[cfg(feature = "tokio")]
+pub async fn fetch_async() -> Option<Vec<report::Progress>> {
+    Howudoin::global().fetch_async().await
 }
 
 /// Flag for cancellation.
+///
+/// This cancels _all_ reports. To cancel a single report, use [`cancel_report`].
 pub fn cancel() {
-    TX.send(|| Cancel);
+    Howudoin::global().cancel()
+}
+
+/// Cancel the report with `id`, along with all of its descendants.
+///
+/// The cancelled reports transition to [`State::Cancelled`](report::State::Cancelled).
+/// Note that cancellation is propagated to the descendants by the consumer loop, so
+/// [`Tx::cancelled`] might not immediately reflect the cancellation.
+///
+/// # Example
+/// ```rust
+/// let parent = howudoin::new().label("Parent");
+/// let child = howudoin::new_with_parent(parent.id());
+///
+/// howudoin::cancel_report(parent.id()); // cancels parent and child
+/// ```
+pub fn cancel_report(id: Id) {
+    Howudoin::global().cancel_report(id)
+}
+
+/// Subscribe to the progress [`Event`](report::Event)s.
+///
+/// The subscription receives the events emitted after subscribing, alongside the installed
+/// consumer, buffering up to 1024 events (see [`Howudoin::subscribe_with_capacity`]).
+/// The current tree can be [`fetch`]ed to start from, or to resynchronise after the subscriber
+/// [lagged](Subscription::lagged).
+/// If the progress reporter has not been [`init`]ialised, `None` is returned.
+///
+/// # Example
+/// ```rust
+/// howudoin::init(howudoin::consumers::Noop::default());
+///
+/// let sub = howudoin::subscribe().unwrap();
+/// std::thread::spawn(move || {
+///     for event in sub.iter() {
+///         println!("{event:?}");
+///     }
+/// });
+///
+/// let rpt = howudoin::new().label("Progress");
+/// ```
+pub fn subscribe() -> Option<Subscription> {
+    Howudoin::global().subscribe()
+}
+
+/// The consumer loop's statistics, such as the panics caught from the consumer.
+///
+/// If the progress reporter has not been [`init`]ialised, `None` is returned.
+///
+/// # Example
+/// ```rust
+/// howudoin::init(howudoin::consumers::Noop::default());
+///
+/// if let Some(stats) = howudoin::stats() {
+///     assert_eq!(stats.panics, 0);
+/// }
+/// ```
+pub fn stats() -> Option<Stats> {
+    Howudoin::global().stats()
 }
 
 /// Check the cancellation flag.
 ///
 /// If the progress reporter has not been [`init`]ialised, `None` is returned.
 pub fn cancelled() -> Option<bool> {
-    let (tx, rx) = bounded(1);
-    TX.send(|| Cancelled(tx));
-    rx.recv_timeout(Duration::from_millis(500)).ok()
+    Howudoin::global().cancelled()
+}
+
+/// Wait until the progress reporter is flagged for cancellation. Requires `tokio` feature.
+///
+/// Returns `true` once [`cancel`] is called, or `false` if the progress reporter is not
+/// [`init`]ialised, or is disabled (or re-initialised) before being cancelled.
+///
+/// # Example
+/// ```rust
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// howudoin::init_async(howudoin::consumers::Noop::default());
+///
+/// tokio::select! {
+///     _ = howudoin::cancelled_async() => println!("cancelled"),
+///     _ = async { /* do work */ } => println!("done"),
+/// }
+/// # }
+/// ```
+#[cfg(feature = "tokio")]
+pub async fn cancelled_async() -> bool {
+    Howudoin::global().cancelled_async().await
 }
 
 /// Reset the progress consumer loop.
@@ -108,39 +327,219 @@ pub fn cancelled() -> Option<bool> {
 /// it with [`disable`]).
 /// Note that it is usually preferable to initialise a new loop with a fresh consumer.
 pub fn reset() {
-    TX.send(|| Reset)
+    Howudoin::global().reset()
 }
 
-pub struct StaticTx(RwLock<Option<Sender<Payload>>>);
+/// The number of tokens in the first bucket of [`Tokens`].
+const FIRST_BUCKET: usize = 32;
+const BUCKETS: usize = (usize::BITS - FIRST_BUCKET.ilog2()) as usize;
 
-impl StaticTx {
-    const fn none() -> Self {
-        StaticTx(parking_lot::const_rwlock(None))
+/// An append-only slab of a consumer loop's tokens.
+///
+/// The tokens are stored in buckets of doubling size, which are allocated on first use and only
+/// freed with the slab, so a token can be looked up without locking.
+#[derive(Debug)]
+pub(crate) struct Tokens {
+    buckets: [AtomicPtr<TokenState>; BUCKETS],
+}
+
+impl Tokens {
+    fn bucket_len(bucket: usize) -> usize {
+        FIRST_BUCKET << bucket
     }
 
-    fn set_tx(&self, tx: Sender<Payload>) {
-        *self.0.write() = Some(tx);
+    /// The bucket and offset of the token at `index`.
+    fn locate(index: usize) -> (usize, usize) {
+        let x = index + FIRST_BUCKET;
+        let bucket = (x.ilog2() - FIRST_BUCKET.ilog2()) as usize;
+        (bucket, x - Self::bucket_len(bucket))
     }
 
-    fn disable(&self) {
-        *self.0.write() = None;
+    /// The token at `index`, if its bucket has been allocated.
+    pub(crate) fn get(&self, index: usize) -> Option<&TokenState> {
+        let (bucket, offset) = Self::locate(index);
+        let ptr = self.buckets[bucket].load(Acquire);
+        // SAFETY: an allocated bucket holds `bucket_len` tokens and lives as long as the slab
+        (!ptr.is_null()).then(|| unsafe { &*ptr.add(offset) })
     }
 
-    fn send<F: FnOnce() -> Payload>(&self, payload: F) {
-        match &*self.0.read() {
-            Some(tx) if !tx.is_disconnected() => tx.send(payload()).ok(),
-            _ => Some(()),
-        };
+    /// The token at `index`, allocating its bucket if needed.
+    pub(crate) fn get_or_alloc(&self, index: usize) -> &TokenState {
+        let (bucket, offset) = Self::locate(index);
+        let ptr = get_or_alloc(&self.buckets[bucket], Self::bucket_len(bucket));
+        // SAFETY: an allocated bucket holds `bucket_len` tokens and lives as long as the slab
+        unsafe { &*ptr.add(offset) }
+    }
+}
+
+impl Default for Tokens {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicPtr::new(null_mut())),
+        }
+    }
+}
+
+impl Drop for Tokens {
+    fn drop(&mut self) {
+        for (bucket, ptr) in self.buckets.iter_mut().enumerate() {
+            let ptr = *ptr.get_mut();
+            if !ptr.is_null() {
+                // SAFETY: the bucket was allocated with `bucket_len` tokens, and is not accessed
+                unsafe { free(ptr, Self::bucket_len(bucket)) };
+            }
+        }
+    }
+}
+
+/// The `len` items behind `ptr`, allocating them on first use.
+///
+/// The allocation never moves, and must be released with [`free`].
+pub(crate) fn get_or_alloc<T: Default>(ptr: &AtomicPtr<T>, len: usize) -> *mut T {
+    let x = ptr.load(Acquire);
+    if !x.is_null() {
+        return x;
+    }
+
+    let new = Box::into_raw((0..len).map(|_| T::default()).collect::<Box<[T]>>()).cast::<T>();
+    match ptr.compare_exchange(null_mut(), new, AcqRel, Acquire) {
+        Ok(_) => new,
+        Err(x) => {
+            // another thread allocated first
+            // SAFETY: allocated above with `len` items, and never shared
+            unsafe { free(new, len) };
+            x
+        }
+    }
+}
+
+/// Release an allocation of `len` items made by [`get_or_alloc`].
+///
+/// # Safety
+/// The allocation must hold `len` items, and must no longer be accessed.
+unsafe fn free<T>(ptr: *mut T, len: usize) {
+    drop(Box::from_raw(slice_from_raw_parts_mut(ptr, len)));
+}
+
+/// A report's token, shared between the [`Tx`] and the consumer loop.
+///
+/// The token is allocated alongside the [`Tx`], which looks it up in the loop's [`Tokens`] by
+/// its id.
+/// The consumer loop flags the token when an ancestor is cancelled, and samples the position,
+/// which the producer updates without sending a message per update.
+#[derive(Debug, Clone)]
+pub(crate) struct Token {
+    tokens: Arc<Tokens>,
+    index: usize,
+}
+
+impl Token {
+    pub(crate) fn new(tokens: Arc<Tokens>, index: usize) -> Self {
+        Self { tokens, index }
+    }
+}
+
+impl Deref for Token {
+    type Target = TokenState;
+
+    fn deref(&self) -> &TokenState {
+        self.tokens.get_or_alloc(self.index)
+    }
+}
+
+/// The state of a report's [`Token`].
+#[derive(Debug, Default)]
+pub(crate) struct TokenState {
+    cancelled: AtomicBool,
+    pos: AtomicU64,
+    /// The position has changed since the consumer loop last sampled it.
+    dirty: AtomicBool,
+}
+
+impl TokenState {
+    /// Increment the position, returning if the consumer loop needs to be notified.
+    fn inc_pos(&self, delta: u64) -> bool {
+        self.pos.fetch_add(delta, Relaxed);
+        self.mark_dirty()
+    }
+
+    /// Set the position, returning if the consumer loop needs to be notified.
+    fn set_pos(&self, pos: u64) -> bool {
+        self.pos.store(pos, Relaxed);
+        self.mark_dirty()
+    }
+
+    fn mark_dirty(&self) -> bool {
+        // a stale `true` only delays the update, the consumer loop samples every report when
+        // flushing
+        !self.dirty.load(Relaxed) && !self.dirty.swap(true, Relaxed)
+    }
+
+    fn clear_dirty(&self) {
+        self.dirty.store(false, Relaxed);
+    }
+
+    /// Sample the position, clearing the dirty flag.
+    pub(crate) fn take_pos(&self) -> u64 {
+        self.dirty.store(false, Relaxed);
+        self.pos.load(Relaxed)
+    }
+
+    pub(crate) fn set_cancelled(&self, cancelled: bool) {
+        self.cancelled.store(cancelled, Relaxed);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Relaxed)
     }
 }
 
 /// The progress reporter transmitter.
+///
+/// A reporter sends its updates to the [`Howudoin`] instance that created it.
+/// The instance is found through the report's id, so the reporter is `Copy`.
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub struct Tx {
     id: Id,
 }
 
 impl Tx {
+    pub(crate) fn new(id: Id) -> Self {
+        Self { id }
+    }
+
+    /// A reporter which is not connected to a consumer loop.
+    pub(crate) fn void() -> Self {
+        Self { id: 0 }
+    }
+
+    /// The instance this reporter belongs to, if its consumer loop is active.
+    pub(crate) fn instance(&self) -> Option<Howudoin> {
+        Howudoin::with_loop(self.id, Shared::instance).flatten()
+    }
+
+    /// Access the report's token and the loop's shared state, if the loop is active.
+    fn with_token<T, F: FnOnce(&Shared, &TokenState) -> T>(&self, f: F) -> Option<T> {
+        Howudoin::with_loop(self.id, |shared| {
+            shared.token(self.id).map(|x| f(shared, x))
+        })
+        .flatten()
+    }
+
+    fn send<F: FnOnce() -> Payload>(&self, payload: F) {
+        if let Some(hd) = self.instance() {
+            hd.send(payload);
+        }
+    }
+
+    /// Notify the consumer loop that the position has changed.
+    fn pos_changed(&self) {
+        if !self.instance().is_some_and(|hd| hd.send(|| PosChanged)) {
+            // not sent, allow the next update to notify
+            self.with_token(|_, x| x.clear_dirty());
+        }
+    }
+
     /// The distinct ID.
     pub fn id(&self) -> Id {
         self.id
@@ -152,7 +551,7 @@ impl Tx {
     /// howudoin::new().label("Report A");
     /// ```
     pub fn label<L: Into<String>>(self, label: L) -> Self {
-        TX.send(|| SetLabel(self.id, label.into()));
+        self.send(|| SetLabel(self.id, label.into()));
         self
     }
 
@@ -164,13 +563,13 @@ impl Tx {
     /// howudoin::new().set_len(100);
     /// ```
     pub fn set_len<L: Into<Option<u64>>>(self, len: L) -> Self {
-        TX.send(|| SetLen(self.id, len.into()));
+        self.send(|| SetLen(self.id, len.into()));
         self
     }
 
     /// Flag to format the position as bytes.
     pub fn fmt_as_bytes(self, fmt_as_bytes: bool) -> Self {
-        TX.send(|| SetFmtBytes(self.id, fmt_as_bytes));
+        self.send(|| SetFmtBytes(self.id, fmt_as_bytes));
         self
     }
 
@@ -181,7 +580,7 @@ impl Tx {
     /// a.desc("processing");
     /// ```
     pub fn desc<D: Into<String>>(&self, desc: D) -> &Self {
-        TX.send(|| SetDesc(self.id, desc.into()));
+        self.send(|| SetDesc(self.id, desc.into()));
         self
     }
 
@@ -192,8 +591,7 @@ impl Tx {
     /// a.inc();
     /// ```
     pub fn inc(&self) -> &Self {
-        TX.send(|| Inc(self.id, 1));
-        self
+        self.inc_by(1_u8)
     }
 
     /// Increment the report position by `delta`.
@@ -203,7 +601,9 @@ impl Tx {
     /// a.inc_by(5_u8);
     /// ```
     pub fn inc_by<P: Into<u64>>(&self, delta: P) -> &Self {
-        TX.send(|| Inc(self.id, delta.into()));
+        if self.with_token(|_, x| x.inc_pos(delta.into())) == Some(true) {
+            self.pos_changed();
+        }
         self
     }
 
@@ -214,7 +614,9 @@ impl Tx {
     /// a.set_pos(5_u8);
     /// ```
     pub fn set_pos<P: Into<u64>>(&self, pos: P) -> &Self {
-        TX.send(|| SetPos(self.id, pos.into()));
+        if self.with_token(|_, x| x.set_pos(pos.into())) == Some(true) {
+            self.pos_changed();
+        }
         self
     }
 
@@ -253,24 +655,166 @@ impl Tx {
     /// These messages are accumulated against a progress report, and consumers can display them
     /// for additional information.
     pub fn add_accum<M: Into<String>>(&self, severity: report::Severity, msg: M) -> &Self {
-        TX.send(|| Accum(self.id, severity, msg.into()));
+        self.send(|| Accum(self.id, severity, msg.into()));
         self
     }
 
-    /// Check if the consumer loop has been flagged for cancellation.
+    /// Check if this report has been flagged for cancellation.
     ///
+    /// A report is cancelled if it, or any of its ancestors, have been cancelled, or if the
+    /// consumer loop has been flagged for cancellation with [`cancel`].
     /// It is up to the producer to decide what to do if cancellation is detected.
+    ///
+    /// Checking the cancellation is cheap (an atomic load), so it can be done in tight loops.
     pub fn cancelled(&self) -> bool {
-        cancelled().unwrap_or(false)
+        self.with_token(|shared, x| shared.is_cancelled() || x.is_cancelled())
+            .unwrap_or_default()
+    }
+
+    /// Cancel this report, along with all of its descendants.
+    ///
+    /// ```rust
+    /// let a = howudoin::new();
+    /// a.cancel();
+    /// ```
+    pub fn cancel(&self) -> &Self {
+        self.with_token(|_, x| x.set_cancelled(true));
+        self.send(|| Cancel(Some(self.id)));
+        self
     }
 
     /// Mark this report as finished but should be kept displayed.
     pub fn finish(self) {
-        TX.send(|| Finish(self.id))
+        self.send(|| Finish(self.id))
     }
 
     /// Mark this report as finished and should be removed from display.
     pub fn close(self) {
-        TX.send(|| Close(self.id))
+        self.send(|| Close(self.id))
+    }
+
+    /// Mark this report as abandoned; it was not finished but will not progress further.
+    pub fn abandon(self) {
+        self.send(|| Abandon(self.id))
+    }
+
+    /// Wrap this reporter in a [`ReportGuard`], which will [`OnDrop::Abandon`] the report if it is
+    /// dropped before being finished.
+    ///
+    /// ```rust
+    /// let rpt = howudoin::new().label("Report A").guard();
+    /// ```
+    pub fn guard(self) -> ReportGuard {
+        ReportGuard {
+            tx: self,
+            on_drop: Some(OnDrop::default()),
+        }
+    }
+}
+
+/// The action a [`ReportGuard`] takes when dropped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum OnDrop {
+    /// Mark the report as finished, see [`Tx::finish`].
+    Finish,
+    /// Mark the report as finished and remove it from display, see [`Tx::close`].
+    Close,
+    /// Mark the report as [`State::Abandoned`](report::State::Abandoned), see [`Tx::abandon`].
+    #[default]
+    Abandon,
+    /// Cancel the report and its descendants, see [`Tx::cancel`].
+    Cancel,
+}
+
+/// A progress reporter which finalises the report when dropped.
+///
+/// [`Tx`] is `Copy` and does nothing when it goes out of scope, so an early return can leave an
+/// in-progress report around forever.
+/// A `ReportGuard` owns the reporter and applies the [`OnDrop`] action if it is dropped without
+/// being [`finish`](Self::finish)ed or [`close`](Self::close)d.
+///
+/// The guard dereferences to [`Tx`] for the reporting methods.
+///
+/// ```rust
+/// let rpt = howudoin::new_guarded()
+///     .label("Report")
+///     .on_drop(howudoin::OnDrop::Close);
+///
+/// rpt.inc();
+/// // rpt is closed when dropped
+/// ```
+#[must_use = "dropping the guard will finalise the report"]
+pub struct ReportGuard {
+    tx: Tx,
+    on_drop: Option<OnDrop>,
+}
+
+impl ReportGuard {
+    /// Set the action to take when the guard is dropped.
+    pub fn on_drop(mut self, on_drop: OnDrop) -> Self {
+        self.on_drop = Some(on_drop);
+        self
+    }
+
+    /// Set the label/name of the report.
+    ///
+    /// See [`Tx::label`].
+    pub fn label<L: Into<String>>(self, label: L) -> Self {
+        self.tx.label(label);
+        self
+    }
+
+    /// Set an upper bound on the progress.
+    ///
+    /// See [`Tx::set_len`].
+    pub fn set_len<L: Into<Option<u64>>>(self, len: L) -> Self {
+        self.tx.set_len(len);
+        self
+    }
+
+    /// Flag to format the position as bytes.
+    ///
+    /// See [`Tx::fmt_as_bytes`].
+    pub fn fmt_as_bytes(self, fmt_as_bytes: bool) -> Self {
+        self.tx.fmt_as_bytes(fmt_as_bytes);
+        self
+    }
+
+    /// Mark this report as finished but should be kept displayed.
+    pub fn finish(self) {
+        self.into_tx().finish()
+    }
+
+    /// Mark this report as finished and should be removed from display.
+    pub fn close(self) {
+        self.into_tx().close()
+    }
+
+    /// Release the reporter without applying the drop action.
+    pub fn into_tx(mut self) -> Tx {
+        self.on_drop = None;
+        self.tx
+    }
+}
+
+impl std::ops::Deref for ReportGuard {
+    type Target = Tx;
+
+    fn deref(&self) -> &Tx {
+        &self.tx
+    }
+}
+
+impl Drop for ReportGuard {
+    fn drop(&mut self) {
+        match self.on_drop {
+            Some(OnDrop::Finish) => self.tx.finish(),
+            Some(OnDrop::Close) => self.tx.close(),
+            Some(OnDrop::Abandon) => self.tx.abandon(),
+            Some(OnDrop::Cancel) => {
+                self.tx.cancel();
+            }
+            None => (),
+        }
     }
 }
@@ -1,5 +1,5 @@
 set -e
 
-cargo t --all-features -- --test-threads=1
+cargo t --all-features
 cargo fmt --check
 cargo clippy -- -D warnings
\ No newline at end of file